@@ -4,25 +4,27 @@ use syn::{
     parse::{Parse, ParseStream},
     parse_macro_input,
     punctuated::Punctuated,
-    DeriveInput, Ident, LitStr, Token,
+    DeriveInput, Ident, LitStr, Path, Token,
 };
 
-struct Extensions {
+struct Args {
     extensions: Vec<LitStr>,
+    validate: Option<Path>,
 }
 
-impl Extensions {
-    const NAME: &'static str = "extensions";
+impl Args {
+    const EXTENSIONS: &'static str = "extensions";
+    const VALIDATE: &'static str = "validate";
 }
 
-impl Parse for Extensions {
+impl Parse for Args {
     fn parse(input: ParseStream) -> syn::Result<Self> {
         // Parse the ident and '='
         let ident: Ident = input.parse()?;
         input.parse::<Token![=]>()?;
 
-        if ident != Self::NAME {
-            panic!()
+        if ident != Self::EXTENSIONS {
+            return Err(syn::Error::new(ident.span(), "expected `extensions`"));
         }
 
         // Parse the '['
@@ -32,8 +34,20 @@ impl Parse for Extensions {
         // Parse the extensions
         let extensions = Punctuated::<LitStr, Token![,]>::parse_terminated(&content)?;
 
-        Ok(Extensions {
+        // Parse the optional `, validate = path`
+        let mut validate = None;
+        if input.parse::<Option<Token![,]>>()?.is_some() && !input.is_empty() {
+            let ident: Ident = input.parse()?;
+            if ident != Self::VALIDATE {
+                return Err(syn::Error::new(ident.span(), "expected `validate`"));
+            }
+            input.parse::<Token![=]>()?;
+            validate = Some(input.parse()?);
+        }
+
+        Ok(Args {
             extensions: extensions.into_iter().collect(),
+            validate,
         })
     }
 }
@@ -47,7 +61,8 @@ impl Parse for Extensions {
 /// use bevy::utils::thiserror;
 ///
 /// // then use this macro on a struct like this:
-/// #[auto_asset_loader(extensions = ["some.ron", "some.else.ron"])]
+/// // (`validate` is optional, see below)
+/// #[auto_asset_loader(extensions = ["some.ron", "some.else.ron"], validate = SomeAsset::validate)]
 /// #[derive(Deserialize, Debug)]
 /// pub struct SomeAsset {
 ///     health: u32,
@@ -69,12 +84,27 @@ impl Parse for Extensions {
 ///    /// A [RON](ron) Error
 ///    #[error("Could not parse RON: {0}")]
 ///    RonSpannedError(#[from] ron::error::SpannedError),
+///    /// The error returned by `validate`, only with `validate`
+///    #[error("Invalid asset: {0}")]
+///    Invalid(Box<dyn std::error::Error + Send + Sync>),
 /// }
 ///
 /// // a unit struct for the AssetLoader
 /// #[derive(Default)]
 /// pub struct SomeAssetLoader;
 ///
+/// impl SomeAssetLoader {
+///     /// Parses an asset the way the loader does, for loading it from anywhere else.
+///     pub fn parse(bytes: &[u8]) -> Result<SomeAsset, SomeAssetLoaderError> {
+///         let mut custom_asset = ron::de::from_bytes::<SomeAsset>(bytes)?;
+///         // only with `validate`, which takes `&mut SomeAsset` and returns a `Result<(), E>`
+///         // with any `E` that converts into a boxed error
+///         SomeAsset::validate(&mut custom_asset)
+///             .map_err(|e| SomeAssetLoaderError::Invalid(e.into()))?;
+///         Ok(custom_asset)
+///     }
+/// }
+///
 /// impl bevy::asset::AssetLoader for SomeAssetLoader {
 ///     type Asset = SomeAsset;
 ///     type Settings = ();
@@ -88,8 +118,7 @@ impl Parse for Extensions {
 ///            Box::pin(async move {
 ///                let mut bytes = Vec::new();
 ///                reader.read_to_end(&mut bytes).await?;
-///             let custom_asset = ron::de::from_bytes::<SomeAsset>(&bytes)?;
-///             Ok(custom_asset)
+///             Self::parse(&bytes)
 ///         })
 ///     }
 ///     fn extensions(&self) -> &[&str] {
@@ -107,7 +136,7 @@ impl Parse for Extensions {
 /// ```
 #[proc_macro_attribute]
 pub fn auto_ron_asset_loader(args: TokenStream, input: TokenStream) -> TokenStream {
-    let args = parse_macro_input!(args as Extensions);
+    let args = parse_macro_input!(args as Args);
     let input = parse_macro_input!(input as DeriveInput);
 
     let extensions_values = args
@@ -122,6 +151,20 @@ pub fn auto_ron_asset_loader(args: TokenStream, input: TokenStream) -> TokenStre
     let loader_error_name = quote::format_ident!("{}Error", loader_name);
     let plugin_name = quote::format_ident!("{}Plugin", asset_type);
 
+    let (invalid_variant, validation) = match &args.validate {
+        Some(validate) => (
+            quote! {
+                /// The error returned by the validation
+                #[error("Invalid asset: {0}")]
+                Invalid(Box<dyn std::error::Error + Send + Sync>),
+            },
+            quote! {
+                #validate(&mut custom_asset).map_err(|e| #loader_error_name::Invalid(e.into()))?;
+            },
+        ),
+        None => (quote! {}, quote! {}),
+    };
+
     let expanded = quote! {
 
         #[derive(Asset, TypePath)] // auto derive necessary derives
@@ -136,12 +179,23 @@ pub fn auto_ron_asset_loader(args: TokenStream, input: TokenStream) -> TokenStre
             /// A [RON](ron) Error
             #[error("Could not parse RON: {0}")]
             RonSpannedError(#[from] ron::error::SpannedError),
+            #invalid_variant
         }
 
         // a unit struct for the AssetLoader
         #[derive(Default)]
         pub struct #loader_name;
 
+        impl #loader_name {
+            /// Parses an asset the way the loader does, for loading it from anywhere else.
+            #[allow(unused_mut)]
+            pub fn parse(bytes: &[u8]) -> Result<#asset_type, #loader_error_name> {
+                let mut custom_asset = ron::de::from_bytes::<#asset_type>(bytes)?;
+                #validation
+                Ok(custom_asset)
+            }
+        }
+
         impl bevy::asset::AssetLoader for #loader_name {
             type Asset = #asset_type;
             type Settings = ();
@@ -156,8 +210,7 @@ pub fn auto_ron_asset_loader(args: TokenStream, input: TokenStream) -> TokenStre
                 Box::pin(async move {
                     let mut bytes = Vec::new();
                     reader.read_to_end(&mut bytes).await?;
-                    let custom_asset = ron::de::from_bytes::<#asset_type>(&bytes)?;
-                    Ok(custom_asset)
+                    Self::parse(&bytes)
                 })
             }
 
@@ -9,7 +9,28 @@ pub struct InternalAudioPlugin;
 impl Plugin for InternalAudioPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(AudioPlugin)
-            .add_systems(OnEnter(GameState::Playing), start_audio);
+            .add_systems(
+                OnTransition {
+                    from: GameState::Menu,
+                    to: GameState::Playing,
+                },
+                start_audio,
+            )
+            .add_systems(OnEnter(GameState::Paused), pause_audio)
+            .add_systems(
+                OnTransition {
+                    from: GameState::Paused,
+                    to: GameState::Playing,
+                },
+                resume_audio,
+            )
+            .add_systems(
+                OnTransition {
+                    from: GameState::Paused,
+                    to: GameState::Menu,
+                },
+                stop_audio,
+            );
     }
 }
 
@@ -25,3 +46,26 @@ fn start_audio(mut commands: Commands, audio_assets: Res<AudioAssets>, audio: Re
         .handle();
     commands.insert_resource(FlyingAudio(handle));
 }
+
+fn pause_audio(flying: Res<FlyingAudio>, mut audio_instances: ResMut<Assets<AudioInstance>>) {
+    if let Some(instance) = audio_instances.get_mut(&flying.0) {
+        instance.pause(AudioTween::default());
+    }
+}
+
+fn resume_audio(flying: Res<FlyingAudio>, mut audio_instances: ResMut<Assets<AudioInstance>>) {
+    if let Some(instance) = audio_instances.get_mut(&flying.0) {
+        instance.resume(AudioTween::default());
+    }
+}
+
+fn stop_audio(
+    mut commands: Commands,
+    flying: Res<FlyingAudio>,
+    mut audio_instances: ResMut<Assets<AudioInstance>>,
+) {
+    if let Some(instance) = audio_instances.get_mut(&flying.0) {
+        instance.stop(AudioTween::default());
+    }
+    commands.remove_resource::<FlyingAudio>();
+}
@@ -0,0 +1,622 @@
+use crate::menu::{ButtonColors, ChangeState};
+use crate::GameState;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+pub struct BindingsPlugin;
+
+/// This plugin is responsible for the [`InputBindings`]: loading them from the user's config directory,
+/// and the controls screen during the State `GameState::Controls`, which rebinds them and saves them again.
+impl Plugin for BindingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(InputBindings::load())
+            .init_resource::<Rebinding>()
+            .add_systems(OnEnter(GameState::Controls), setup_controls)
+            .add_systems(
+                Update,
+                (
+                    click_binding_button,
+                    click_reset_bindings_button,
+                    rebind,
+                    update_binding_labels,
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Controls)),
+            )
+            .add_systems(
+                OnExit(GameState::Controls),
+                (save_bindings, cleanup_controls),
+            );
+    }
+}
+
+/// The abstract actions of a player, which are bound to keys.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputAction {
+    Left,
+    Right,
+    Jump,
+    Dash,
+    /// Held while jumping to drop through one-way platforms.
+    Down,
+}
+
+impl InputAction {
+    pub const ALL: [Self; 5] = [Self::Left, Self::Right, Self::Jump, Self::Dash, Self::Down];
+}
+
+/// Which of the [`KeyBindings`] a keyboard player uses. Several players can share a keyboard
+/// by using different schemes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyboardScheme {
+    /// The whole keyboard, for a single player.
+    Full,
+    /// The left half of a shared keyboard.
+    Left,
+    /// The right half of a shared keyboard.
+    Right,
+}
+
+impl KeyboardScheme {
+    pub const ALL: [Self; 3] = [Self::Full, Self::Left, Self::Right];
+}
+
+/// The keys bound to every [`InputAction`] of a [`KeyboardScheme`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct KeyBindings {
+    pub left: Vec<KeyCode>,
+    pub right: Vec<KeyCode>,
+    pub jump: Vec<KeyCode>,
+    pub dash: Vec<KeyCode>,
+    #[serde(default)]
+    pub down: Vec<KeyCode>,
+}
+
+impl KeyBindings {
+    pub fn action(&self, action: InputAction) -> &[KeyCode] {
+        match action {
+            InputAction::Left => &self.left,
+            InputAction::Right => &self.right,
+            InputAction::Jump => &self.jump,
+            InputAction::Dash => &self.dash,
+            InputAction::Down => &self.down,
+        }
+    }
+
+    pub fn action_mut(&mut self, action: InputAction) -> &mut Vec<KeyCode> {
+        match action {
+            InputAction::Left => &mut self.left,
+            InputAction::Right => &mut self.right,
+            InputAction::Jump => &mut self.jump,
+            InputAction::Dash => &mut self.dash,
+            InputAction::Down => &mut self.down,
+        }
+    }
+}
+
+/// The axis and buttons of a gamepad, shared by all gamepads.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GamepadBindings {
+    /// Moves left and right.
+    pub move_axis: GamepadAxisType,
+    /// Pushed down, drops through one-way platforms when jumping.
+    #[serde(default = "default_down_axis")]
+    pub down_axis: GamepadAxisType,
+    pub jump: GamepadButtonType,
+    pub dash: GamepadButtonType,
+    /// How the move axis responds, for gamepads without their own entry in `sticks`.
+    #[serde(default)]
+    pub stick: StickResponse,
+    /// How the move axis responds on specific gamepads, by the name of the gamepad.
+    #[serde(default)]
+    pub sticks: BTreeMap<String, StickResponse>,
+}
+
+fn default_down_axis() -> GamepadAxisType {
+    GamepadAxisType::LeftStickY
+}
+
+impl GamepadBindings {
+    /// The [`StickResponse`] of the gamepad with the given name.
+    pub fn stick(&self, name: Option<&str>) -> &StickResponse {
+        name.and_then(|name| self.sticks.get(name))
+            .unwrap_or(&self.stick)
+    }
+}
+
+/// Maps the raw value of a stick axis to how far a player moves.
+/// Values within the inner deadzone are ignored, so a resting stick that drifts
+/// doesn't move the player, and values beyond the outer deadzone count as fully pressed.
+/// The values in between are scaled to `0.0..=1.0` and shaped by the curve.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StickResponse {
+    pub inner_deadzone: f32,
+    pub outer_deadzone: f32,
+    pub curve: ResponseCurve,
+}
+
+impl Default for StickResponse {
+    fn default() -> Self {
+        Self {
+            inner_deadzone: 0.15,
+            outer_deadzone: 0.95,
+            curve: ResponseCurve::Linear,
+        }
+    }
+}
+
+impl StickResponse {
+    pub fn apply(&self, value: f32) -> f32 {
+        let range = (self.outer_deadzone - self.inner_deadzone).max(f32::EPSILON);
+        let magnitude = ((value.abs() - self.inner_deadzone) / range).clamp(0.0, 1.0);
+        self.curve.apply(magnitude).clamp(0.0, 1.0) * value.signum()
+    }
+}
+
+/// The shape of a [`StickResponse`] between its deadzones.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum ResponseCurve {
+    Linear,
+    /// Finer control for small movements of the stick.
+    Quadratic,
+    /// Linear interpolation between points `(input, output)`, sorted by input.
+    /// The curve starts at `(0.0, 0.0)` and ends at `(1.0, 1.0)`.
+    Custom(Vec<(f32, f32)>),
+}
+
+impl ResponseCurve {
+    /// Shapes a value in `0.0..=1.0`.
+    pub fn apply(&self, value: f32) -> f32 {
+        match self {
+            ResponseCurve::Linear => value,
+            ResponseCurve::Quadratic => value * value,
+            ResponseCurve::Custom(points) => {
+                let points = std::iter::once((0.0, 0.0))
+                    .chain(points.iter().copied())
+                    .chain(std::iter::once((1.0, 1.0)))
+                    .collect::<Vec<_>>();
+                points
+                    .windows(2)
+                    .find(|segment| value <= segment[1].0)
+                    .map_or(1.0, |segment| {
+                        let ((x0, y0), (x1, y1)) = (segment[0], segment[1]);
+                        if x1 <= x0 {
+                            y1
+                        } else {
+                            y0 + (y1 - y0) * (value - x0) / (x1 - x0)
+                        }
+                    })
+            }
+        }
+    }
+}
+
+/// Maps the actions of the players to keys, buttons and axes.
+/// Saved to `bindings.ron` in the user's config directory whenever the controls screen is left.
+#[derive(Resource, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct InputBindings {
+    pub full: KeyBindings,
+    pub left: KeyBindings,
+    pub right: KeyBindings,
+    pub gamepad: GamepadBindings,
+}
+
+impl Default for InputBindings {
+    fn default() -> Self {
+        Self {
+            full: KeyBindings {
+                left: vec![KeyCode::A, KeyCode::Left],
+                right: vec![KeyCode::D, KeyCode::Right],
+                jump: vec![KeyCode::Space],
+                dash: vec![KeyCode::ShiftLeft],
+                down: vec![KeyCode::S, KeyCode::Down],
+            },
+            left: KeyBindings {
+                left: vec![KeyCode::A],
+                right: vec![KeyCode::D],
+                jump: vec![KeyCode::W, KeyCode::Space],
+                dash: vec![KeyCode::ShiftLeft],
+                down: vec![KeyCode::S],
+            },
+            right: KeyBindings {
+                left: vec![KeyCode::Left],
+                right: vec![KeyCode::Right],
+                jump: vec![KeyCode::Up],
+                dash: vec![KeyCode::ShiftRight],
+                down: vec![KeyCode::Down],
+            },
+            gamepad: GamepadBindings {
+                move_axis: GamepadAxisType::LeftStickX,
+                down_axis: default_down_axis(),
+                jump: GamepadButtonType::South,
+                dash: GamepadButtonType::West,
+                stick: StickResponse::default(),
+                sticks: BTreeMap::new(),
+            },
+        }
+    }
+}
+
+impl InputBindings {
+    pub fn keys(&self, scheme: KeyboardScheme) -> &KeyBindings {
+        match scheme {
+            KeyboardScheme::Full => &self.full,
+            KeyboardScheme::Left => &self.left,
+            KeyboardScheme::Right => &self.right,
+        }
+    }
+
+    pub fn keys_mut(&mut self, scheme: KeyboardScheme) -> &mut KeyBindings {
+        match scheme {
+            KeyboardScheme::Full => &mut self.full,
+            KeyboardScheme::Left => &mut self.left,
+            KeyboardScheme::Right => &mut self.right,
+        }
+    }
+
+    /// The file the bindings are saved to, `None` where there is no config directory (like on the web).
+    fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("infini_jump").join("bindings.ron"))
+    }
+
+    /// Loads the saved bindings, falling back to the default bindings if there are none
+    /// or they can't be read.
+    fn load() -> Self {
+        let Some(path) = Self::path() else {
+            return Self::default();
+        };
+        let Ok(bindings) = std::fs::read_to_string(&path) else {
+            info!("No input bindings saved at {path:?}, using the default bindings.");
+            return Self::default();
+        };
+        ron::from_str(&bindings).unwrap_or_else(|error| {
+            warn!("Failed to parse the input bindings at {path:?}, using the default bindings: {error}");
+            Self::default()
+        })
+    }
+
+    fn save(&self) {
+        let Some(path) = Self::path() else {
+            return;
+        };
+        let bindings = match ron::ser::to_string_pretty(self, default()) {
+            Ok(bindings) => bindings,
+            Err(error) => {
+                warn!("Failed to serialize the input bindings: {error}");
+                return;
+            }
+        };
+        if let Err(error) = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::write(&path, bindings))
+        {
+            warn!("Failed to save the input bindings to {path:?}: {error}");
+        }
+    }
+}
+
+/// A binding on the controls screen, pressing its button rebinds it.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+enum Binding {
+    Key(KeyboardScheme, InputAction),
+    GamepadMove,
+    GamepadDown,
+    GamepadButton(InputAction),
+}
+
+/// The binding waiting for a key, button or axis to be bound to it.
+#[derive(Resource, Default)]
+struct Rebinding(Option<Binding>);
+
+#[derive(Component)]
+struct ControlsMenu;
+
+#[derive(Component)]
+struct ResetBindingsButton;
+
+fn setup_controls(mut commands: Commands) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    row_gap: Val::Px(20.0),
+                    ..default()
+                },
+                ..default()
+            },
+            ControlsMenu,
+        ))
+        .with_children(|children| {
+            children
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Row,
+                        column_gap: Val::Px(10.0),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|columns| {
+                    for scheme in KeyboardScheme::ALL {
+                        let title = match scheme {
+                            KeyboardScheme::Full => "Keyboard",
+                            KeyboardScheme::Left => "Left Half",
+                            KeyboardScheme::Right => "Right Half",
+                        };
+                        spawn_column(columns, title, |column| {
+                            for action in InputAction::ALL {
+                                spawn_button(column, "", Binding::Key(scheme, action));
+                            }
+                        });
+                    }
+                    spawn_column(columns, "Gamepad", |column| {
+                        spawn_button(column, "", Binding::GamepadMove);
+                        spawn_button(column, "", Binding::GamepadButton(InputAction::Jump));
+                        spawn_button(column, "", Binding::GamepadButton(InputAction::Dash));
+                        spawn_button(column, "", Binding::GamepadDown);
+                    });
+                });
+            children
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Row,
+                        column_gap: Val::Px(10.0),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|row| {
+                    spawn_button(row, "Reset", ResetBindingsButton);
+                    spawn_button(row, "Back", ChangeState(GameState::Menu));
+                });
+        });
+}
+
+fn spawn_column(
+    columns: &mut ChildBuilder,
+    title: &str,
+    spawn_buttons: impl FnOnce(&mut ChildBuilder),
+) {
+    columns
+        .spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                row_gap: Val::Px(10.0),
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|column| {
+            column.spawn(TextBundle::from_section(title, text_style()));
+            spawn_buttons(column);
+        });
+}
+
+fn text_style() -> TextStyle {
+    TextStyle {
+        font_size: 20.0,
+        color: Color::rgb(0.9, 0.9, 0.9),
+        ..default()
+    }
+}
+
+/// Spawns a button, the labels of [`Binding`]s are filled in by [`update_binding_labels`].
+fn spawn_button(children: &mut ChildBuilder, text: &str, action: impl Bundle) {
+    let button_colors = ButtonColors::default();
+    children
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    width: Val::Px(220.0),
+                    height: Val::Px(40.0),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: button_colors.normal.into(),
+                ..default()
+            },
+            button_colors,
+            action,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(text, text_style()));
+        });
+}
+
+/// Starts rebinding a [`Binding`] when its button is pressed.
+fn click_binding_button(
+    mut rebinding: ResMut<Rebinding>,
+    interaction_query: Query<(&Interaction, &Binding), Changed<Interaction>>,
+) {
+    for (interaction, binding) in &interaction_query {
+        if *interaction == Interaction::Pressed {
+            rebinding.0 = Some(*binding);
+        }
+    }
+}
+
+fn click_reset_bindings_button(
+    mut bindings: ResMut<InputBindings>,
+    mut rebinding: ResMut<Rebinding>,
+    interaction_query: Query<&Interaction, (Changed<Interaction>, With<ResetBindingsButton>)>,
+) {
+    for interaction in &interaction_query {
+        if *interaction == Interaction::Pressed {
+            *bindings = InputBindings::default();
+            rebinding.0 = None;
+        }
+    }
+}
+
+/// Binds the next pressed key, button or moved axis to the [`Binding`] waiting for it.
+/// Escape cancels rebinding, or goes back to the menu if nothing is being rebound.
+fn rebind(
+    mut next_state: ResMut<NextState<GameState>>,
+    mut rebinding: ResMut<Rebinding>,
+    mut bindings: ResMut<InputBindings>,
+    keyboard_input: Res<Input<KeyCode>>,
+    buttons: Res<Input<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+) {
+    let escape = keyboard_input.just_pressed(KeyCode::Escape);
+    let Some(binding) = rebinding.0 else {
+        if escape {
+            next_state.set(GameState::Menu);
+        }
+        return;
+    };
+
+    if escape {
+        rebinding.0 = None;
+        return;
+    }
+
+    match binding {
+        Binding::Key(scheme, action) => {
+            if let Some(key) = keyboard_input.get_just_pressed().next() {
+                *bindings.keys_mut(scheme).action_mut(action) = vec![*key];
+                rebinding.0 = None;
+            }
+        }
+        Binding::GamepadMove | Binding::GamepadDown => {
+            if let Some(axis) = axes
+                .devices()
+                .find(|axis| axes.get(**axis).is_some_and(|value| value.abs() > 0.5))
+            {
+                if binding == Binding::GamepadMove {
+                    bindings.gamepad.move_axis = axis.axis_type;
+                } else {
+                    bindings.gamepad.down_axis = axis.axis_type;
+                }
+                rebinding.0 = None;
+            }
+        }
+        Binding::GamepadButton(action) => {
+            if let Some(button) = buttons.get_just_pressed().next() {
+                match action {
+                    InputAction::Dash => bindings.gamepad.dash = button.button_type,
+                    _ => bindings.gamepad.jump = button.button_type,
+                }
+                rebinding.0 = None;
+            }
+        }
+    }
+}
+
+fn update_binding_labels(
+    bindings: Res<InputBindings>,
+    rebinding: Res<Rebinding>,
+    buttons: Query<(&Binding, &Children)>,
+    mut labels: Query<&mut Text>,
+) {
+    if !bindings.is_changed() && !rebinding.is_changed() {
+        return;
+    }
+
+    for (binding, children) in &buttons {
+        let mut labels = labels.iter_many_mut(children);
+        let Some(mut text) = labels.fetch_next() else {
+            continue;
+        };
+        let name = match binding {
+            Binding::Key(_, action) | Binding::GamepadButton(action) => format!("{action:?}"),
+            Binding::GamepadMove => "Move".to_string(),
+            Binding::GamepadDown => "Down".to_string(),
+        };
+        let bound = if rebinding.0 == Some(*binding) {
+            "...".to_string()
+        } else {
+            match binding {
+                Binding::Key(scheme, action) => bindings
+                    .keys(*scheme)
+                    .action(*action)
+                    .iter()
+                    .map(|key| format!("{key:?}"))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                Binding::GamepadMove => format!("{:?}", bindings.gamepad.move_axis),
+                Binding::GamepadDown => format!("{:?}", bindings.gamepad.down_axis),
+                Binding::GamepadButton(InputAction::Dash) => {
+                    format!("{:?}", bindings.gamepad.dash)
+                }
+                Binding::GamepadButton(_) => format!("{:?}", bindings.gamepad.jump),
+            }
+        };
+        *text = Text::from_section(format!("{name}: {bound}"), text_style());
+    }
+}
+
+fn save_bindings(bindings: Res<InputBindings>) {
+    bindings.save();
+}
+
+fn cleanup_controls(
+    mut commands: Commands,
+    mut rebinding: ResMut<Rebinding>,
+    menu: Query<Entity, With<ControlsMenu>>,
+) {
+    rebinding.0 = None;
+    for entity in menu.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bindings_survive_saving() {
+        let mut bindings = InputBindings::default();
+        *bindings
+            .keys_mut(KeyboardScheme::Right)
+            .action_mut(InputAction::Jump) = vec![KeyCode::Numpad0];
+        bindings.gamepad.dash = GamepadButtonType::RightTrigger;
+
+        bindings.gamepad.sticks.insert(
+            "Xbox Controller".to_string(),
+            StickResponse {
+                curve: ResponseCurve::Custom(vec![(0.5, 0.25)]),
+                ..default()
+            },
+        );
+
+        let saved = ron::ser::to_string_pretty(&bindings, default()).unwrap();
+        assert_eq!(ron::from_str::<InputBindings>(&saved).unwrap(), bindings);
+    }
+
+    #[test]
+    fn sticks_ignore_their_deadzones() {
+        let stick = StickResponse {
+            inner_deadzone: 0.2,
+            outer_deadzone: 0.8,
+            curve: ResponseCurve::Linear,
+        };
+        assert_eq!(stick.apply(0.1), 0.0);
+        assert_eq!(stick.apply(-0.2), 0.0);
+        assert_eq!(stick.apply(0.5), 0.5);
+        assert_eq!(stick.apply(-0.9), -1.0);
+    }
+
+    #[test]
+    fn response_curves_shape_stick_values() {
+        assert_eq!(ResponseCurve::Quadratic.apply(0.5), 0.25);
+
+        let custom = ResponseCurve::Custom(vec![(0.5, 0.2), (0.75, 0.6)]);
+        assert_eq!(custom.apply(0.0), 0.0);
+        assert_eq!(custom.apply(0.25), 0.1);
+        assert_eq!(custom.apply(0.5), 0.2);
+        assert_eq!(custom.apply(0.875), 0.8);
+        assert_eq!(custom.apply(1.0), 1.0);
+    }
+}
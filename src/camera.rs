@@ -0,0 +1,139 @@
+use crate::character_controller::CharacterController;
+use bevy::prelude::*;
+use bevy::transform::TransformSystem;
+use bevy_pancam::{PanCam, PanCamPlugin};
+use bevy_xpbd_2d::{math::*, prelude::*};
+
+pub struct CameraPlugin;
+
+/// This plugin is responsible for the camera following the players, zooming out to keep them all in view.
+/// In debug builds, F1 switches to panning the camera freely with the mouse instead.
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(PanCamPlugin)
+            .add_systems(Startup, camera_setup)
+            // after physics moved the player, before the transforms are propagated
+            .add_systems(
+                PostUpdate,
+                follow_player
+                    .after(PhysicsSet::Sync)
+                    .before(TransformSystem::TransformPropagate),
+            );
+
+        #[cfg(debug_assertions)]
+        {
+            app.add_systems(Update, toggle_free_pan);
+        }
+    }
+}
+
+/// Makes a camera follow the center of all [`CharacterController`]s, unless its [`PanCam`] is enabled.
+#[derive(Component)]
+pub struct CameraFollow {
+    /// How fast the camera catches up, higher is faster. The camera covers
+    /// `1 - e^(-smoothing)` of the distance to its target per second.
+    pub smoothing: Scalar,
+    /// The size of the area around the camera center the player moves in without the camera following.
+    pub dead_zone: Vector,
+    /// How far ahead of the player the camera looks, in seconds of the player's velocity.
+    pub look_ahead: Scalar,
+    /// The look-ahead is never further than this.
+    pub max_look_ahead: Vector,
+    /// The space kept around the players when the camera zooms out to fit them all.
+    pub margin: Vector,
+    /// The camera never zooms out further than this projection scale.
+    pub max_zoom_out: Scalar,
+}
+
+impl Default for CameraFollow {
+    fn default() -> Self {
+        Self {
+            smoothing: 5.0,
+            dead_zone: Vector::new(64.0, 96.0),
+            look_ahead: 0.3,
+            max_look_ahead: Vector::new(160.0, 96.0),
+            margin: Vector::new(320.0, 240.0),
+            max_zoom_out: 3.0,
+        }
+    }
+}
+
+fn camera_setup(mut commands: Commands) {
+    commands.spawn((
+        Camera2dBundle::default(),
+        CameraFollow::default(),
+        PanCam {
+            enabled: false,
+            ..default()
+        },
+    ));
+}
+
+fn follow_player(
+    time: Res<Time>,
+    players: Query<
+        (&Transform, &LinearVelocity),
+        (With<CharacterController>, Without<CameraFollow>),
+    >,
+    mut cameras: Query<(
+        &mut Transform,
+        &mut OrthographicProjection,
+        &CameraFollow,
+        &PanCam,
+    )>,
+) {
+    if players.is_empty() {
+        return;
+    }
+
+    let count = players.iter().len() as Scalar;
+    let (mut min, mut max) = (Vector::MAX, Vector::MIN);
+    let (mut center, mut velocity) = (Vector::ZERO, Vector::ZERO);
+    for (transform, linear_velocity) in &players {
+        let position = transform.translation.truncate();
+        min = min.min(position);
+        max = max.max(position);
+        center += position / count;
+        velocity += linear_velocity.0 / count;
+    }
+
+    for (mut transform, mut projection, follow, pan_cam) in &mut cameras {
+        if pan_cam.enabled {
+            continue;
+        }
+
+        let catch_up = 1.0 - (-follow.smoothing * time.delta_seconds()).exp();
+
+        let look_ahead =
+            (velocity * follow.look_ahead).clamp(-follow.max_look_ahead, follow.max_look_ahead);
+        let target = center + look_ahead;
+        let offset = target - transform.translation.truncate();
+
+        // only the part of the offset that leaves the dead zone is followed
+        let half_dead_zone = follow.dead_zone / 2.0;
+        let offset = offset.signum() * (offset.abs() - half_dead_zone).max(Vector::ZERO);
+
+        transform.translation += (offset * catch_up).extend(0.0);
+
+        // zoom out until the players and the margin around them fit on the screen
+        let unscaled_view = projection.area.size() / projection.scale;
+        if unscaled_view.min_element() <= 0.0 {
+            continue;
+        }
+        let fit = (max - min + follow.margin) / unscaled_view;
+        let scale = fit.max_element().clamp(1.0, follow.max_zoom_out);
+        projection.scale += (scale - projection.scale) * catch_up;
+    }
+}
+
+#[cfg(debug_assertions)]
+fn toggle_free_pan(input: Res<Input<KeyCode>>, mut pan_cams: Query<&mut PanCam>) {
+    if !input.just_pressed(KeyCode::F1) {
+        return;
+    }
+
+    for mut pan_cam in &mut pan_cams {
+        pan_cam.enabled = !pan_cam.enabled;
+        info!("Free pan camera enabled: {}", pan_cam.enabled);
+    }
+}
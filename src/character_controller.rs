@@ -1,22 +1,32 @@
-use bevy::{ecs::query::Has, prelude::*};
+use bevy::{ecs::query::Has, prelude::*, utils::HashMap};
 use bevy_xpbd_2d::{math::*, prelude::*, SubstepSchedule, SubstepSet};
 
+use crate::bindings::{InputBindings, KeyboardScheme};
+use crate::GameState;
+
+/// Moves the character controllers, reading the [`InputBindings`] of the
+/// [`BindingsPlugin`](crate::bindings::BindingsPlugin), which has to be added as well.
 pub struct CharacterControllerPlugin;
 
 impl Plugin for CharacterControllerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_event::<MovementAction>()
+        app.add_event::<MovementEvent>()
             .add_systems(
                 Update,
                 (
                     keyboard_input,
                     gamepad_input,
                     update_grounded,
+                    update_touching_wall,
+                    dash,
+                    // the other systems leave dashing character controllers alone
+                    apply_deferred,
                     apply_gravity,
                     movement,
                     apply_movement_damping,
                 )
-                    .chain(),
+                    .chain()
+                    .run_if(in_state(GameState::Playing)),
             )
             .add_systems(
                 // Run collision handling in substep schedule
@@ -26,11 +36,62 @@ impl Plugin for CharacterControllerPlugin {
     }
 }
 
-/// An event sent for a movement input action.
+/// An event sent for a movement input action of a character controller.
 #[derive(Event)]
+pub struct MovementEvent {
+    pub entity: Entity,
+    pub action: MovementAction,
+}
+
+/// A movement input action.
 pub enum MovementAction {
     Move(Scalar),
     Jump,
+    /// The jump button was let go, which cuts the jump short.
+    JumpReleased,
+    /// Dashes in the direction the character controller is moving in.
+    Dash,
+    /// Down is held, jumping while holding it drops through [`OneWayPlatform`]s.
+    Down,
+}
+
+/// The [`MovementAction`]s sent for one character controller during a frame.
+/// The move direction is clamped, so a player moving with several inputs at once isn't faster.
+#[derive(Default)]
+struct Actions {
+    move_direction: Scalar,
+    jump: bool,
+    jump_released: bool,
+    dash: bool,
+    down: bool,
+}
+
+impl Actions {
+    fn read(movement_event_reader: &mut EventReader<MovementEvent>) -> HashMap<Entity, Self> {
+        let mut actions = HashMap::<Entity, Self>::new();
+        for event in movement_event_reader.read() {
+            let actions = actions.entry(event.entity).or_default();
+            match event.action {
+                MovementAction::Move(direction) => actions.move_direction += direction,
+                MovementAction::Jump => actions.jump = true,
+                MovementAction::JumpReleased => actions.jump_released = true,
+                MovementAction::Dash => actions.dash = true,
+                MovementAction::Down => actions.down = true,
+            }
+        }
+        for actions in actions.values_mut() {
+            actions.move_direction = actions.move_direction.clamp(-1.0, 1.0);
+        }
+        actions
+    }
+}
+
+/// Which inputs control a character controller. Several players can share a keyboard
+/// with different [`KeyboardScheme`]s, but every gamepad belongs to a single player.
+#[derive(Component, Default)]
+pub struct PlayerInput {
+    pub keyboard: Option<KeyboardScheme>,
+    pub gamepad: Option<Gamepad>,
 }
 
 /// A marker component indicating that an entity is using a character controller.
@@ -41,6 +102,133 @@ pub struct CharacterController;
 #[derive(Component)]
 #[component(storage = "SparseSet")]
 pub struct Grounded;
+
+/// How far a character controller may sink into a [`OneWayPlatform`] and still land on it.
+const ONE_WAY_TOLERANCE: Scalar = 8.0;
+/// How long dropping through [`OneWayPlatform`]s lasts, in seconds.
+const DROP_THROUGH_TIME: Scalar = 0.25;
+
+/// A marker component for colliders that character controllers only collide with from above.
+/// They jump up through them, and drop down through them by jumping while holding down.
+#[derive(Component)]
+pub struct OneWayPlatform;
+
+/// A marker component indicating that an entity is [`Grounded`] only on [`OneWayPlatform`]s,
+/// so it can drop through.
+#[derive(Component)]
+#[component(storage = "SparseSet")]
+pub struct OnOneWayPlatform;
+
+/// A component indicating that an entity is dropping through [`OneWayPlatform`]s.
+#[derive(Component)]
+#[component(storage = "SparseSet")]
+pub struct DroppingThrough {
+    pub remaining: Scalar,
+}
+
+/// Whether a character controller with `collider` at `position` lands on a [`OneWayPlatform`]
+/// with the given AABB instead of passing through it.
+fn lands_on_platform(
+    collider: &Collider,
+    position: Vector,
+    rotation: &Rotation,
+    linear_velocity: Vector,
+    platform: &ColliderAabb,
+) -> bool {
+    let bottom = collider
+        .compute_aabb(position, rotation.as_radians())
+        .mins
+        .y;
+    linear_velocity.y <= 0.0 && bottom >= platform.maxs.y - ONE_WAY_TOLERANCE
+}
+
+/// A component indicating that an entity touches a wall that's too steep to stand on,
+/// with the direction of the wall on the X axis, `-1.0` for left and `1.0` for right.
+#[derive(Component)]
+#[component(storage = "SparseSet")]
+pub struct TouchingWall(pub Scalar);
+
+/// How long after walking off a ledge a character controller can still jump, in seconds.
+#[derive(Component)]
+pub struct CoyoteTime {
+    duration: Scalar,
+    remaining: Scalar,
+}
+
+impl CoyoteTime {
+    pub fn new(duration: Scalar) -> Self {
+        Self {
+            duration,
+            remaining: 0.0,
+        }
+    }
+}
+
+/// How long a jump pressed before landing is remembered, in seconds.
+/// The character controller jumps as soon as it can within that time.
+#[derive(Component)]
+pub struct JumpBuffer {
+    duration: Scalar,
+    remaining: Scalar,
+}
+
+impl JumpBuffer {
+    pub fn new(duration: Scalar) -> Self {
+        Self {
+            duration,
+            remaining: 0.0,
+        }
+    }
+}
+
+/// A dash: a horizontal burst of `speed` lasting `duration` seconds, that can be used again
+/// `cooldown` seconds after it started.
+#[derive(Component)]
+pub struct DashAbility {
+    speed: Scalar,
+    duration: Scalar,
+    cooldown: Scalar,
+    cooldown_remaining: Scalar,
+}
+
+impl DashAbility {
+    pub fn new(speed: Scalar, duration: Scalar, cooldown: Scalar) -> Self {
+        Self {
+            speed,
+            duration,
+            cooldown,
+            cooldown_remaining: 0.0,
+        }
+    }
+}
+
+/// A marker component indicating that an entity is dashing. While dashing, the velocity is fixed,
+/// gravity and damping don't apply, and hazards should leave the entity alone.
+#[derive(Component)]
+#[component(storage = "SparseSet")]
+pub struct Dashing {
+    velocity: Vector,
+    remaining: Scalar,
+}
+
+/// How many times a character controller can jump in mid-air before landing again.
+#[derive(Component)]
+pub struct AirJumps {
+    max: u32,
+    remaining: u32,
+}
+
+impl AirJumps {
+    pub fn new(max: u32) -> Self {
+        Self { max, remaining: 0 }
+    }
+}
+
+/// The upward velocity of a character controller is multiplied by this when the jump is released,
+/// so holding the jump jumps higher. `1.0` always jumps the full height.
+#[derive(Component)]
+pub struct JumpCutMultiplier(Scalar);
+
 /// The acceleration used for character movement.
 #[derive(Component)]
 pub struct MovementAcceleration(Scalar);
@@ -63,6 +251,14 @@ pub struct ControllerGravity(Vector);
 #[derive(Component)]
 pub struct MaxSlopeAngle(Scalar);
 
+/// The fastest a character controller falls while pressing into a wall.
+#[derive(Component)]
+pub struct WallSlideSpeed(Scalar);
+
+/// The velocity of a jump off a wall, with X pointing away from the wall.
+#[derive(Component)]
+pub struct WallJumpImpulse(Vector);
+
 /// A bundle that contains the components needed for a basic
 /// kinematic character controller.
 #[derive(Bundle)]
@@ -72,6 +268,11 @@ pub struct CharacterControllerBundle {
     collider: Collider,
     ground_caster: ShapeCaster,
     gravity: ControllerGravity,
+    coyote_time: CoyoteTime,
+    jump_buffer: JumpBuffer,
+    jump_cut: JumpCutMultiplier,
+    air_jumps: AirJumps,
+    dash: DashAbility,
     movement: MovementBundle,
 }
 
@@ -82,6 +283,8 @@ pub struct MovementBundle {
     damping: MovementDampingFactor,
     jump_impulse: JumpImpulse,
     max_slope_angle: MaxSlopeAngle,
+    wall_slide_speed: WallSlideSpeed,
+    wall_jump_impulse: WallJumpImpulse,
 }
 
 impl MovementBundle {
@@ -96,8 +299,26 @@ impl MovementBundle {
             damping: MovementDampingFactor(damping),
             jump_impulse: JumpImpulse(jump_impulse),
             max_slope_angle: MaxSlopeAngle(max_slope_angle),
+            wall_slide_speed: WallSlideSpeed(100.0),
+            wall_jump_impulse: WallJumpImpulse(Vector::new(300.0, 400.0)),
         }
     }
+
+    pub fn with_wall_movement(mut self, slide_speed: Scalar, jump_impulse: Vector) -> Self {
+        self.wall_slide_speed = WallSlideSpeed(slide_speed);
+        self.wall_jump_impulse = WallJumpImpulse(jump_impulse);
+        self
+    }
+
+    /// The [`JumpArc`] of a character controller moving with this bundle under `gravity`.
+    pub fn jump_arc(&self, gravity: Vector) -> JumpArc {
+        JumpArc::new(
+            &self.jump_impulse,
+            &ControllerGravity(gravity),
+            &self.acceleration,
+            &self.damping,
+        )
+    }
 }
 
 impl Default for MovementBundle {
@@ -106,6 +327,58 @@ impl Default for MovementBundle {
     }
 }
 
+/// How far a character controller gets with a single jump.
+///
+/// Used by level generators to only place platforms the player can actually reach.
+/// As a resource, it's the player's, and passed on to the generators through their config.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct JumpArc {
+    /// The vertical speed right after jumping.
+    jump_speed: Scalar,
+    /// The strength of the gravity pulling the character back down.
+    gravity: Scalar,
+    /// The horizontal speed the character settles at while holding a direction.
+    run_speed: Scalar,
+}
+
+impl JumpArc {
+    /// [`MovementDampingFactor`] is applied once per frame, so the run speed depends on the frame rate.
+    /// This is the frame time the run speed is calculated for.
+    const FRAME_TIME: Scalar = 1.0 / 60.0;
+
+    pub fn new(
+        jump_impulse: &JumpImpulse,
+        gravity: &ControllerGravity,
+        acceleration: &MovementAcceleration,
+        damping: &MovementDampingFactor,
+    ) -> Self {
+        // each frame adds `acceleration * FRAME_TIME` and then multiplies by `damping`,
+        // which settles where both cancel out
+        let damping = damping.0.clamp(0.0, 0.999);
+        Self {
+            jump_speed: jump_impulse.0,
+            gravity: gravity.0.length(),
+            run_speed: acceleration.0 * Self::FRAME_TIME * damping / (1.0 - damping),
+        }
+    }
+
+    /// The height of the apex of a jump, relative to where it started.
+    pub fn max_height(&self) -> Scalar {
+        self.jump_speed * self.jump_speed / (2.0 * self.gravity)
+    }
+
+    /// The horizontal distance covered by a jump at full speed until it comes down at `height`
+    /// relative to where it started, or `None` if `height` is out of reach.
+    pub fn distance_at(&self, height: Scalar) -> Option<Scalar> {
+        let discriminant = self.jump_speed * self.jump_speed - 2.0 * self.gravity * height;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let air_time = (self.jump_speed + discriminant.sqrt()) / self.gravity;
+        Some(self.run_speed * air_time)
+    }
+}
+
 impl CharacterControllerBundle {
     pub fn new(collider: Collider, gravity: Vector) -> Self {
         // Create shape caster as a slightly smaller version of collider
@@ -124,10 +397,45 @@ impl CharacterControllerBundle {
             )
             .with_max_time_of_impact(10.0),
             gravity: ControllerGravity(gravity),
+            coyote_time: CoyoteTime::new(0.1),
+            jump_buffer: JumpBuffer::new(0.1),
+            jump_cut: JumpCutMultiplier(0.5),
+            air_jumps: AirJumps::new(0),
+            dash: DashAbility::new(600.0, 0.15, 0.6),
             movement: MovementBundle::default(),
         }
     }
 
+    pub fn with_wall_movement(mut self, slide_speed: Scalar, jump_impulse: Vector) -> Self {
+        self.movement = self.movement.with_wall_movement(slide_speed, jump_impulse);
+        self
+    }
+
+    pub fn with_coyote_time(mut self, duration: Scalar) -> Self {
+        self.coyote_time = CoyoteTime::new(duration);
+        self
+    }
+
+    pub fn with_jump_buffer(mut self, duration: Scalar) -> Self {
+        self.jump_buffer = JumpBuffer::new(duration);
+        self
+    }
+
+    pub fn with_jump_cut(mut self, multiplier: Scalar) -> Self {
+        self.jump_cut = JumpCutMultiplier(multiplier);
+        self
+    }
+
+    pub fn with_air_jumps(mut self, max: u32) -> Self {
+        self.air_jumps = AirJumps::new(max);
+        self
+    }
+
+    pub fn with_dash(mut self, speed: Scalar, duration: Scalar, cooldown: Scalar) -> Self {
+        self.dash = DashAbility::new(speed, duration, cooldown);
+        self
+    }
+
     pub fn with_movement(
         mut self,
         acceleration: Scalar,
@@ -135,115 +443,441 @@ impl CharacterControllerBundle {
         jump_impulse: Scalar,
         max_slope_angle: Scalar,
     ) -> Self {
-        self.movement = MovementBundle::new(acceleration, damping, jump_impulse, max_slope_angle);
+        // the wall movement is set separately, and stays as it is
+        self.movement = MovementBundle {
+            wall_slide_speed: self.movement.wall_slide_speed,
+            wall_jump_impulse: self.movement.wall_jump_impulse,
+            ..MovementBundle::new(acceleration, damping, jump_impulse, max_slope_angle)
+        };
         self
     }
 }
 
-/// Sends [`MovementAction`] events based on keyboard input.
+/// Sends [`MovementEvent`]s based on the keyboard scheme of every [`PlayerInput`].
 fn keyboard_input(
-    mut movement_event_writer: EventWriter<MovementAction>,
+    mut movement_event_writer: EventWriter<MovementEvent>,
     keyboard_input: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    players: Query<(Entity, &PlayerInput)>,
 ) {
-    let left = keyboard_input.any_pressed([KeyCode::A, KeyCode::Left]);
-    let right = keyboard_input.any_pressed([KeyCode::D, KeyCode::Right]);
+    for (entity, input) in &players {
+        let Some(scheme) = input.keyboard else {
+            continue;
+        };
+        let keys = bindings.keys(scheme);
+        let mut send = |action| movement_event_writer.send(MovementEvent { entity, action });
 
-    let horizontal = right as i8 - left as i8;
-    let direction = horizontal as Scalar;
+        let left = keyboard_input.any_pressed(keys.left.iter().copied());
+        let right = keyboard_input.any_pressed(keys.right.iter().copied());
 
-    if direction != 0.0 {
-        movement_event_writer.send(MovementAction::Move(direction));
-    }
+        let horizontal = right as i8 - left as i8;
+        let direction = horizontal as Scalar;
+
+        if direction != 0.0 {
+            send(MovementAction::Move(direction));
+        }
 
-    if keyboard_input.just_pressed(KeyCode::Space) {
-        movement_event_writer.send(MovementAction::Jump);
+        if keyboard_input.any_just_pressed(keys.jump.iter().copied()) {
+            send(MovementAction::Jump);
+        }
+
+        if keyboard_input.any_just_released(keys.jump.iter().copied()) {
+            send(MovementAction::JumpReleased);
+        }
+
+        if keyboard_input.any_just_pressed(keys.dash.iter().copied()) {
+            send(MovementAction::Dash);
+        }
+
+        if keyboard_input.any_pressed(keys.down.iter().copied()) {
+            send(MovementAction::Down);
+        }
     }
 }
 
-/// Sends [`MovementAction`] events based on gamepad input.
+/// Sends [`MovementEvent`]s based on the gamepad of every [`PlayerInput`].
 fn gamepad_input(
-    mut movement_event_writer: EventWriter<MovementAction>,
-    gamepads: Res<Gamepads>,
+    mut movement_event_writer: EventWriter<MovementEvent>,
     axes: Res<Axis<GamepadAxis>>,
     buttons: Res<Input<GamepadButton>>,
+    bindings: Res<InputBindings>,
+    gamepads: Res<Gamepads>,
+    players: Query<(Entity, &PlayerInput)>,
 ) {
-    for gamepad in gamepads.iter() {
-        let axis_lx = GamepadAxis {
+    for (entity, input) in &players {
+        let Some(gamepad) = input.gamepad else {
+            continue;
+        };
+        let mut send = |action| movement_event_writer.send(MovementEvent { entity, action });
+
+        let move_axis = GamepadAxis {
             gamepad,
-            axis_type: GamepadAxisType::LeftStickX,
+            axis_type: bindings.gamepad.move_axis,
         };
+        let stick = bindings.gamepad.stick(gamepads.name(gamepad));
 
-        if let Some(x) = axes.get(axis_lx) {
-            movement_event_writer.send(MovementAction::Move(x as Scalar));
+        if let Some(x) = axes.get(move_axis).map(|x| stick.apply(x)) {
+            if x != 0.0 {
+                send(MovementAction::Move(x as Scalar));
+            }
+        }
+
+        let down_axis = GamepadAxis {
+            gamepad,
+            axis_type: bindings.gamepad.down_axis,
+        };
+
+        if axes.get(down_axis).is_some_and(|y| stick.apply(y) < -0.5) {
+            send(MovementAction::Down);
         }
 
         let jump_button = GamepadButton {
             gamepad,
-            button_type: GamepadButtonType::South,
+            button_type: bindings.gamepad.jump,
         };
 
         if buttons.just_pressed(jump_button) {
-            movement_event_writer.send(MovementAction::Jump);
+            send(MovementAction::Jump);
+        }
+
+        if buttons.just_released(jump_button) {
+            send(MovementAction::JumpReleased);
+        }
+
+        let dash_button = GamepadButton {
+            gamepad,
+            button_type: bindings.gamepad.dash,
+        };
+
+        if buttons.just_pressed(dash_button) {
+            send(MovementAction::Dash);
         }
     }
 }
 
-/// Updates the [`Grounded`] status for character controllers.
+/// Updates the [`Grounded`] status for character controllers,
+/// starts their [`CoyoteTime`] when they leave the ground and gives back their [`AirJumps`] on landing.
+/// [`OneWayPlatform`]s only count as ground when the character would land on them.
 fn update_grounded(
     mut commands: Commands,
+    time: Res<Time>,
     mut query: Query<
-        (Entity, &ShapeHits, &Rotation, Option<&MaxSlopeAngle>),
+        (
+            Entity,
+            &ShapeHits,
+            &Collider,
+            &Position,
+            &Rotation,
+            &LinearVelocity,
+            Option<&MaxSlopeAngle>,
+            Option<&mut CoyoteTime>,
+            Option<&mut AirJumps>,
+            Option<&mut DroppingThrough>,
+        ),
         With<CharacterController>,
     >,
+    platforms: Query<&ColliderAabb, With<OneWayPlatform>>,
 ) {
-    for (entity, hits, rotation, max_slope_angle) in &mut query {
+    let delta_time = time.delta_seconds_f64().adjust_precision();
+
+    for (
+        entity,
+        hits,
+        collider,
+        position,
+        rotation,
+        linear_velocity,
+        max_slope_angle,
+        coyote_time,
+        air_jumps,
+        dropping_through,
+    ) in &mut query
+    {
+        let is_dropping = if let Some(mut dropping_through) = dropping_through {
+            dropping_through.remaining -= delta_time;
+            if dropping_through.remaining <= 0.0 {
+                commands.entity(entity).remove::<DroppingThrough>();
+            }
+            true
+        } else {
+            false
+        };
+
         // The character is grounded if the shape caster has a hit with a normal
         // that isn't too steep.
-        let is_grounded = hits.iter().any(|hit| {
+        let ground = hits.iter().filter(|hit| {
             if let Some(angle) = max_slope_angle {
                 rotation.rotate(-hit.normal2).angle_between(Vector::Y).abs() <= angle.0
             } else {
                 true
             }
         });
+        let (mut is_grounded, mut on_solid_ground) = (false, false);
+        for hit in ground {
+            match platforms.get(hit.entity) {
+                Ok(platform) => {
+                    is_grounded |= !is_dropping
+                        && lands_on_platform(
+                            collider,
+                            position.0,
+                            rotation,
+                            linear_velocity.0,
+                            platform,
+                        );
+                }
+                Err(_) => {
+                    is_grounded = true;
+                    on_solid_ground = true;
+                }
+            }
+        }
 
         if is_grounded {
             commands.entity(entity).insert(Grounded);
         } else {
             commands.entity(entity).remove::<Grounded>();
         }
+        if is_grounded && !on_solid_ground {
+            commands.entity(entity).insert(OnOneWayPlatform);
+        } else {
+            commands.entity(entity).remove::<OnOneWayPlatform>();
+        }
+
+        // the ground is still in reach right after jumping, which must not count as landing
+        let landed = is_grounded && linear_velocity.y <= 0.0;
+        if let Some(mut coyote_time) = coyote_time {
+            if landed {
+                coyote_time.remaining = coyote_time.duration;
+            } else {
+                coyote_time.remaining = (coyote_time.remaining - delta_time).max(0.0);
+            }
+        }
+        if let Some(mut air_jumps) = air_jumps.filter(|_| landed) {
+            air_jumps.remaining = air_jumps.max;
+        }
+    }
+}
+
+/// Updates the [`TouchingWall`] status for character controllers,
+/// by casting their collider a little to the left and right.
+/// The sides of [`OneWayPlatform`]s aren't walls.
+fn update_touching_wall(
+    mut commands: Commands,
+    spatial_query: SpatialQuery,
+    platforms: Query<(), With<OneWayPlatform>>,
+    query: Query<
+        (
+            Entity,
+            &Collider,
+            &Position,
+            &Rotation,
+            Option<&MaxSlopeAngle>,
+        ),
+        With<CharacterController>,
+    >,
+) {
+    for (entity, collider, position, rotation, max_slope_angle) in &query {
+        let max_slope_angle = max_slope_angle.map_or(PI / 4.0, |angle| angle.0);
+        let wall = [-1.0, 1.0].into_iter().find(|direction| {
+            spatial_query
+                .shape_hits(
+                    collider,
+                    position.0,
+                    rotation.as_radians(),
+                    Vector::X * *direction,
+                    1.0,
+                    4,
+                    true,
+                    SpatialQueryFilter::new().without_entities([entity]),
+                )
+                .iter()
+                .filter(|hit| !platforms.contains(hit.entity))
+                // anything that is too steep to stand on is a wall
+                .any(|hit| {
+                    rotation.rotate(-hit.normal2).angle_between(Vector::Y).abs() > max_slope_angle
+                })
+        });
+
+        if let Some(direction) = wall {
+            commands.entity(entity).insert(TouchingWall(direction));
+        } else {
+            commands.entity(entity).remove::<TouchingWall>();
+        }
     }
 }
 
-/// Responds to [`MovementAction`] events and moves character controllers accordingly.
+/// Responds to [`MovementEvent`]s and moves their character controllers accordingly.
+///
+/// Jumps are possible while [`Grounded`] or during [`CoyoteTime`],
+/// and jumps pressed too early are kept in the [`JumpBuffer`].
+/// Releasing the jump while still going up applies the [`JumpCutMultiplier`].
+///
+/// In the air, pressing into a wall slides down it with at most the [`WallSlideSpeed`],
+/// and jumping while [`TouchingWall`] jumps off with the [`WallJumpImpulse`].
+/// Anywhere else in the air, jumping uses up one of the [`AirJumps`].
+/// Jumping while holding down on a [`OneWayPlatform`] drops through it instead.
 fn movement(
+    mut commands: Commands,
     time: Res<Time>,
-    mut movement_event_reader: EventReader<MovementAction>,
+    mut movement_event_reader: EventReader<MovementEvent>,
+    mut controllers: Query<
+        (
+            Entity,
+            &MovementAcceleration,
+            &JumpImpulse,
+            &mut LinearVelocity,
+            Has<Grounded>,
+            Has<OnOneWayPlatform>,
+            Option<&mut CoyoteTime>,
+            Option<&mut JumpBuffer>,
+            Option<&JumpCutMultiplier>,
+            Option<&TouchingWall>,
+            Option<&WallSlideSpeed>,
+            Option<&WallJumpImpulse>,
+            Option<&mut AirJumps>,
+        ),
+        Without<Dashing>,
+    >,
+) {
+    // Precision is adjusted so that the example works with
+    // both the `f32` and `f64` features. Otherwise you don't need this.
+    let delta_time = time.delta_seconds_f64().adjust_precision();
+
+    let mut actions = Actions::read(&mut movement_event_reader);
+
+    for (
+        entity,
+        movement_acceleration,
+        jump_impulse,
+        mut linear_velocity,
+        is_grounded,
+        on_one_way_platform,
+        mut coyote_time,
+        mut jump_buffer,
+        jump_cut,
+        touching_wall,
+        wall_slide_speed,
+        wall_jump_impulse,
+        air_jumps,
+    ) in &mut controllers
+    {
+        let actions = actions.remove(&entity).unwrap_or_default();
+
+        linear_velocity.x += actions.move_direction * movement_acceleration.0 * delta_time;
+
+        if let Some(jump_cut) = jump_cut.filter(|_| actions.jump_released) {
+            if linear_velocity.y > 0.0 {
+                linear_velocity.y *= jump_cut.0;
+            }
+        }
+
+        let wall = touching_wall
+            .filter(|_| !is_grounded)
+            .map(|touching_wall| touching_wall.0);
+
+        if let Some(jump_buffer) = jump_buffer.as_deref_mut() {
+            jump_buffer.remaining = if actions.jump {
+                jump_buffer.duration
+            } else {
+                (jump_buffer.remaining - delta_time).max(0.0)
+            };
+        }
+
+        let wants_jump = actions.jump
+            || jump_buffer
+                .as_ref()
+                .is_some_and(|jump_buffer| jump_buffer.remaining > 0.0);
+        let can_jump = is_grounded
+            || coyote_time
+                .as_ref()
+                .is_some_and(|coyote_time| coyote_time.remaining > 0.0);
+
+        if actions.jump && actions.down && on_one_way_platform {
+            commands.entity(entity).insert(DroppingThrough {
+                remaining: DROP_THROUGH_TIME,
+            });
+            if let Some(jump_buffer) = jump_buffer.as_deref_mut() {
+                jump_buffer.remaining = 0.0;
+            }
+            if let Some(coyote_time) = coyote_time.as_deref_mut() {
+                coyote_time.remaining = 0.0;
+            }
+        } else if wants_jump && can_jump {
+            linear_velocity.y = jump_impulse.0;
+            if let Some(jump_buffer) = jump_buffer.as_deref_mut() {
+                jump_buffer.remaining = 0.0;
+            }
+            if let Some(coyote_time) = coyote_time.as_deref_mut() {
+                coyote_time.remaining = 0.0;
+            }
+        } else if let (true, Some(wall), Some(wall_jump_impulse)) =
+            (wants_jump, wall, wall_jump_impulse)
+        {
+            linear_velocity.x = -wall * wall_jump_impulse.0.x;
+            linear_velocity.y = wall_jump_impulse.0.y;
+            if let Some(jump_buffer) = jump_buffer.as_deref_mut() {
+                jump_buffer.remaining = 0.0;
+            }
+        } else if let Some(mut air_jumps) =
+            air_jumps.filter(|air_jumps| wants_jump && air_jumps.remaining > 0)
+        {
+            linear_velocity.y = jump_impulse.0;
+            air_jumps.remaining -= 1;
+            if let Some(jump_buffer) = jump_buffer.as_deref_mut() {
+                jump_buffer.remaining = 0.0;
+            }
+        } else if let (Some(wall), Some(wall_slide_speed)) = (wall, wall_slide_speed) {
+            if actions.move_direction * wall > 0.0 {
+                linear_velocity.y = linear_velocity.y.max(-wall_slide_speed.0);
+            }
+        }
+    }
+}
+
+/// Starts dashes on [`MovementAction::Dash`] and keeps the velocity of [`Dashing`] character controllers
+/// fixed until their dash is over.
+fn dash(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut movement_event_reader: EventReader<MovementEvent>,
     mut controllers: Query<(
-        &MovementAcceleration,
-        &JumpImpulse,
+        Entity,
+        &mut DashAbility,
         &mut LinearVelocity,
-        Has<Grounded>,
+        Option<&mut Dashing>,
     )>,
 ) {
     // Precision is adjusted so that the example works with
     // both the `f32` and `f64` features. Otherwise you don't need this.
     let delta_time = time.delta_seconds_f64().adjust_precision();
 
-    for event in movement_event_reader.read() {
-        for (movement_acceleration, jump_impulse, mut linear_velocity, is_grounded) in
-            &mut controllers
-        {
-            match event {
-                MovementAction::Move(direction) => {
-                    linear_velocity.x += *direction * movement_acceleration.0 * delta_time;
-                }
-                MovementAction::Jump => {
-                    if is_grounded {
-                        linear_velocity.y = jump_impulse.0;
-                    }
-                }
+    let mut actions = Actions::read(&mut movement_event_reader);
+
+    for (entity, mut dash, mut linear_velocity, dashing) in &mut controllers {
+        let actions = actions.remove(&entity).unwrap_or_default();
+        dash.cooldown_remaining = (dash.cooldown_remaining - delta_time).max(0.0);
+
+        if let Some(mut dashing) = dashing {
+            dashing.remaining -= delta_time;
+            if dashing.remaining <= 0.0 {
+                commands.entity(entity).remove::<Dashing>();
+            } else {
+                linear_velocity.0 = dashing.velocity;
             }
+        } else if actions.dash && dash.cooldown_remaining <= 0.0 {
+            // dash where the input points, or keep going where the character is going
+            let direction = [actions.move_direction, linear_velocity.x]
+                .into_iter()
+                .find(|direction| *direction != 0.0)
+                .map_or(1.0, Scalar::signum);
+            let velocity = Vector::X * direction * dash.speed;
+
+            linear_velocity.0 = velocity;
+            dash.cooldown_remaining = dash.cooldown;
+            commands.entity(entity).insert(Dashing {
+                velocity,
+                remaining: dash.duration,
+            });
         }
     }
 }
@@ -251,7 +885,7 @@ fn movement(
 /// Applies [`ControllerGravity`] to character controllers.
 fn apply_gravity(
     time: Res<Time>,
-    mut controllers: Query<(&ControllerGravity, &mut LinearVelocity)>,
+    mut controllers: Query<(&ControllerGravity, &mut LinearVelocity), Without<Dashing>>,
 ) {
     // Precision is adjusted so that the example works with
     // both the `f32` and `f64` features. Otherwise you don't need this.
@@ -263,7 +897,9 @@ fn apply_gravity(
 }
 
 /// Slows down movement in the X direction.
-fn apply_movement_damping(mut query: Query<(&MovementDampingFactor, &mut LinearVelocity)>) {
+fn apply_movement_damping(
+    mut query: Query<(&MovementDampingFactor, &mut LinearVelocity), Without<Dashing>>,
+) {
     for (damping_factor, mut linear_velocity) in &mut query {
         // We could use `LinearDamping`, but we don't want to dampen movement along the Y axis
         linear_velocity.x *= damping_factor.0;
@@ -276,6 +912,7 @@ fn apply_movement_damping(mut query: Query<(&MovementDampingFactor, &mut LinearV
 /// This system performs very basic collision response for kinematic
 /// character controllers by pushing them along their contact normals
 /// by the current penetration depths.
+/// Contacts with [`OneWayPlatform`]s are ignored unless the character lands on them.
 #[allow(clippy::type_complexity)]
 fn kinematic_controller_collisions(
     collisions: Res<Collisions>,
@@ -283,13 +920,16 @@ fn kinematic_controller_collisions(
     mut character_controllers: Query<
         (
             &RigidBody,
+            &Collider,
             &mut Position,
             &Rotation,
             &mut LinearVelocity,
             Option<&MaxSlopeAngle>,
+            Has<DroppingThrough>,
         ),
         With<CharacterController>,
     >,
+    platforms: Query<&ColliderAabb, With<OneWayPlatform>>,
 ) {
     // Iterate through collisions and move the kinematic body to resolve penetration
     for contacts in collisions.iter() {
@@ -308,22 +948,42 @@ fn kinematic_controller_collisions(
         // Get the body of the character controller and whether it is the first
         // or second entity in the collision.
         let is_first: bool;
-        let (rb, mut position, rotation, mut linear_velocity, max_slope_angle) =
-            if let Ok(character) = character_controllers.get_mut(collider_parent1.get()) {
-                is_first = true;
-                character
-            } else if let Ok(character) = character_controllers.get_mut(collider_parent2.get()) {
-                is_first = false;
-                character
-            } else {
-                continue;
-            };
+        let (
+            rb,
+            collider,
+            mut position,
+            rotation,
+            mut linear_velocity,
+            max_slope_angle,
+            is_dropping,
+        ) = if let Ok(character) = character_controllers.get_mut(collider_parent1.get()) {
+            is_first = true;
+            character
+        } else if let Ok(character) = character_controllers.get_mut(collider_parent2.get()) {
+            is_first = false;
+            character
+        } else {
+            continue;
+        };
 
         // This system only handles collision response for kinematic character controllers
         if !rb.is_kinematic() {
             continue;
         }
 
+        let other = if is_first {
+            contacts.entity2
+        } else {
+            contacts.entity1
+        };
+        if let Ok(platform) = platforms.get(other) {
+            if is_dropping
+                || !lands_on_platform(collider, position.0, rotation, linear_velocity.0, platform)
+            {
+                continue;
+            }
+        }
+
         // Iterate through contact manifolds and their contacts.
         // Each contact in a single manifold shares the same contact normal.
         for manifold in contacts.manifolds.iter() {
@@ -348,3 +1008,403 @@ fn kinematic_controller_collisions(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRAVITY: Vector = Vector::new(0.0, -1000.0);
+    const JUMP_IMPULSE: Scalar = 400.0;
+    /// The height of the capsule's center when it stands on the ground.
+    const STANDING: Scalar = 22.5;
+
+    fn app() -> App {
+        let mut app = crate::testing::app();
+        // ground with its top at y = 0, ending at x = 0
+        app.world.spawn((
+            RigidBody::Static,
+            Collider::cuboid(1000.0, 20.0),
+            TransformBundle::from_transform(Transform::from_xyz(-500.0, -10.0, 0.0)),
+        ));
+        app
+    }
+
+    fn spawn_character(
+        app: &mut App,
+        pos: Vector,
+        coyote_time: Scalar,
+        jump_buffer: Scalar,
+    ) -> Entity {
+        app.world
+            .spawn((
+                TransformBundle::from_transform(Transform::from_translation(pos.extend(0.0))),
+                CharacterControllerBundle::new(Collider::capsule(20.0, 12.5), GRAVITY)
+                    .with_movement(0.0, 0.9, JUMP_IMPULSE, PI / 6.0)
+                    .with_coyote_time(coyote_time)
+                    .with_jump_buffer(jump_buffer),
+            ))
+            .id()
+    }
+
+    fn send(app: &mut App, entity: Entity, action: MovementAction) {
+        app.world.send_event(MovementEvent { entity, action });
+    }
+
+    fn velocity(app: &App, entity: Entity) -> Vector {
+        app.world.get::<LinearVelocity>(entity).unwrap().0
+    }
+
+    fn height(app: &App, entity: Entity) -> Scalar {
+        app.world.get::<Transform>(entity).unwrap().translation.y
+    }
+
+    fn jumped(app: &App, entity: Entity) -> bool {
+        velocity(app, entity).y == JUMP_IMPULSE
+    }
+
+    /// Lets the character settle on the ground.
+    fn stand(app: &mut App, entity: Entity) {
+        for _ in 0..30 {
+            app.update();
+        }
+        assert!(app.world.get::<Grounded>(entity).is_some());
+    }
+
+    /// Moves the character past the end of the ground.
+    fn walk_off_ledge(app: &mut App, entity: Entity) {
+        app.world.get_mut::<Position>(entity).unwrap().x = 100.0;
+    }
+
+    #[test]
+    fn jumps_when_grounded() {
+        let mut app = app();
+        let character = spawn_character(&mut app, Vector::new(-100.0, STANDING), 0.0, 0.0);
+        stand(&mut app, character);
+
+        send(&mut app, character, MovementAction::Jump);
+        app.update();
+        assert!(jumped(&app, character));
+    }
+
+    #[test]
+    fn actions_only_move_their_character() {
+        let mut app = app();
+        let character = spawn_character(&mut app, Vector::new(-100.0, STANDING), 0.0, 0.0);
+        let other = spawn_character(&mut app, Vector::new(-200.0, STANDING), 0.0, 0.0);
+        stand(&mut app, character);
+        stand(&mut app, other);
+
+        send(&mut app, character, MovementAction::Jump);
+        app.update();
+        assert!(jumped(&app, character));
+        assert!(!jumped(&app, other));
+    }
+
+    #[test]
+    fn moving_with_several_inputs_is_not_faster() {
+        let speed = |inputs: usize| {
+            let mut app = app();
+            let character = app
+                .world
+                .spawn((
+                    TransformBundle::from_transform(Transform::from_xyz(-100.0, 2000.0, 0.0)),
+                    CharacterControllerBundle::new(Collider::capsule(20.0, 12.5), GRAVITY)
+                        .with_movement(1000.0, 0.9, JUMP_IMPULSE, PI / 6.0),
+                ))
+                .id();
+            app.update();
+
+            for _ in 0..inputs {
+                send(&mut app, character, MovementAction::Move(1.0));
+            }
+            app.update();
+            velocity(&app, character).x
+        };
+
+        assert!(speed(1) > 0.0);
+        assert_eq!(speed(1), speed(2));
+    }
+
+    #[test]
+    fn jumps_during_coyote_time() {
+        let mut app = app();
+        let character = spawn_character(&mut app, Vector::new(-100.0, STANDING), 0.1, 0.0);
+        stand(&mut app, character);
+
+        walk_off_ledge(&mut app, character);
+        for _ in 0..3 {
+            app.update();
+        }
+        assert!(app.world.get::<Grounded>(character).is_none());
+
+        send(&mut app, character, MovementAction::Jump);
+        app.update();
+        assert!(jumped(&app, character));
+    }
+
+    #[test]
+    fn does_not_jump_after_coyote_time() {
+        let mut app = app();
+        let character = spawn_character(&mut app, Vector::new(-100.0, STANDING), 0.1, 0.0);
+        stand(&mut app, character);
+
+        walk_off_ledge(&mut app, character);
+        for _ in 0..10 {
+            app.update();
+        }
+
+        send(&mut app, character, MovementAction::Jump);
+        app.update();
+        assert!(!jumped(&app, character));
+    }
+
+    #[test]
+    fn coyote_time_allows_only_one_jump() {
+        let mut app = app();
+        let character = spawn_character(&mut app, Vector::new(-100.0, STANDING), 0.1, 0.0);
+        stand(&mut app, character);
+
+        send(&mut app, character, MovementAction::Jump);
+        app.update();
+        assert!(jumped(&app, character));
+
+        // out of reach of the ground, but still within the coyote time of the jump
+        for _ in 0..3 {
+            app.update();
+        }
+        send(&mut app, character, MovementAction::Jump);
+        app.update();
+        assert!(!jumped(&app, character));
+    }
+
+    #[test]
+    fn releasing_jump_cuts_it_short() {
+        let rise = |release: bool| {
+            let mut app = app();
+            let character = spawn_character(&mut app, Vector::new(-100.0, STANDING), 0.0, 0.0);
+            stand(&mut app, character);
+
+            send(&mut app, character, MovementAction::Jump);
+            app.update();
+            if release {
+                send(&mut app, character, MovementAction::JumpReleased);
+            }
+            let mut apex = height(&app, character);
+            for _ in 0..60 {
+                app.update();
+                apex = apex.max(height(&app, character));
+            }
+            apex - STANDING
+        };
+
+        let (full, cut) = (rise(false), rise(true));
+        assert!(cut < full * 0.5, "{cut} isn't much lower than {full}");
+    }
+
+    #[test]
+    fn air_jumps_reset_on_landing() {
+        let mut app = app();
+        let character = app
+            .world
+            .spawn((
+                TransformBundle::from_transform(Transform::from_xyz(-100.0, STANDING, 0.0)),
+                CharacterControllerBundle::new(Collider::capsule(20.0, 12.5), GRAVITY)
+                    .with_movement(0.0, 0.9, JUMP_IMPULSE, PI / 6.0)
+                    .with_coyote_time(0.0)
+                    .with_jump_buffer(0.0)
+                    .with_air_jumps(1),
+            ))
+            .id();
+
+        for _ in 0..2 {
+            stand(&mut app, character);
+            send(&mut app, character, MovementAction::Jump);
+            app.update();
+            assert!(jumped(&app, character));
+
+            let mut air_jumps = 0;
+            for _ in 0..3 {
+                for _ in 0..5 {
+                    app.update();
+                }
+                send(&mut app, character, MovementAction::Jump);
+                app.update();
+                air_jumps += jumped(&app, character) as u32;
+            }
+            assert_eq!(air_jumps, 1);
+
+            // land again
+            for _ in 0..120 {
+                app.update();
+            }
+        }
+    }
+
+    #[test]
+    fn dashes_ignore_gravity_until_they_are_over() {
+        let mut app = app();
+        let character = app
+            .world
+            .spawn((
+                TransformBundle::from_transform(Transform::from_xyz(-100.0, 2000.0, 0.0)),
+                CharacterControllerBundle::new(Collider::capsule(20.0, 12.5), GRAVITY)
+                    .with_dash(600.0, 0.1, 0.5),
+            ))
+            .id();
+        app.update();
+
+        send(&mut app, character, MovementAction::Move(-1.0));
+        send(&mut app, character, MovementAction::Dash);
+        for _ in 0..5 {
+            app.update();
+            assert!(app.world.get::<Dashing>(character).is_some());
+            assert_eq!(velocity(&app, character), Vector::new(-600.0, 0.0));
+        }
+
+        for _ in 0..3 {
+            app.update();
+        }
+        assert!(app.world.get::<Dashing>(character).is_none());
+        assert!(velocity(&app, character).y < 0.0);
+
+        // still cooling down
+        send(&mut app, character, MovementAction::Dash);
+        app.update();
+        assert!(app.world.get::<Dashing>(character).is_none());
+    }
+
+    /// Spawns a wall right of the character, high above the ground.
+    #[test]
+    fn builder_order_does_not_matter() {
+        let bundle = |wall_first: bool| {
+            let bundle = CharacterControllerBundle::new(Collider::capsule(20.0, 12.5), GRAVITY);
+            let movement = |bundle: CharacterControllerBundle| {
+                bundle.with_movement(10.0, 0.8, JUMP_IMPULSE, PI / 4.0)
+            };
+            let wall = |bundle: CharacterControllerBundle| {
+                bundle.with_wall_movement(50.0, Vector::new(200.0, 300.0))
+            };
+            let bundle = if wall_first {
+                movement(wall(bundle))
+            } else {
+                wall(movement(bundle))
+            };
+            let movement = bundle.movement;
+            (
+                movement.acceleration.0,
+                movement.jump_impulse.0,
+                movement.wall_slide_speed.0,
+                movement.wall_jump_impulse.0,
+            )
+        };
+        assert_eq!(bundle(true), bundle(false));
+        assert_eq!(bundle(true).2, 50.0);
+    }
+
+    fn wall_next_to_character(app: &mut App) -> Entity {
+        app.world.spawn((
+            RigidBody::Static,
+            Collider::cuboid(20.0, 1000.0),
+            TransformBundle::from_transform(Transform::from_xyz(-77.5, 2000.0, 0.0)),
+        ));
+        let character = spawn_character(app, Vector::new(-100.0, 2000.0), 0.0, 0.0);
+        // the wall is only known to spatial queries once physics ran
+        for _ in 0..2 {
+            app.update();
+        }
+        character
+    }
+
+    #[test]
+    fn slides_down_walls_when_pressing_into_them() {
+        let mut app = app();
+        let character = wall_next_to_character(&mut app);
+        assert_eq!(
+            app.world.get::<TouchingWall>(character).map(|wall| wall.0),
+            Some(1.0)
+        );
+
+        for _ in 0..30 {
+            send(&mut app, character, MovementAction::Move(1.0));
+            app.update();
+        }
+        assert!(app.world.get::<TouchingWall>(character).is_some());
+        assert!(velocity(&app, character).y >= -100.0);
+    }
+
+    #[test]
+    fn jumps_off_walls() {
+        let mut app = app();
+        let character = wall_next_to_character(&mut app);
+
+        send(&mut app, character, MovementAction::Jump);
+        app.update();
+        let velocity = velocity(&app, character);
+        assert!(velocity.x < 0.0);
+        assert!(velocity.y > 0.0);
+    }
+
+    #[test]
+    fn jumps_up_through_and_drops_down_through_one_way_platforms() {
+        let mut app = app();
+        // the platform's top is at y = 65, low enough to jump onto
+        app.world.spawn((
+            RigidBody::Static,
+            Collider::cuboid(200.0, 20.0),
+            OneWayPlatform,
+            TransformBundle::from_transform(Transform::from_xyz(-100.0, 55.0, 0.0)),
+        ));
+        let character = spawn_character(&mut app, Vector::new(-100.0, STANDING), 0.0, 0.0);
+        stand(&mut app, character);
+
+        send(&mut app, character, MovementAction::Jump);
+        for _ in 0..90 {
+            app.update();
+        }
+        assert!(app.world.get::<OnOneWayPlatform>(character).is_some());
+        assert!(height(&app, character) > 65.0);
+
+        send(&mut app, character, MovementAction::Down);
+        send(&mut app, character, MovementAction::Jump);
+        app.update();
+        assert!(!jumped(&app, character));
+        for _ in 0..60 {
+            app.update();
+        }
+        assert!(height(&app, character) < 45.0);
+        assert!(app.world.get::<Grounded>(character).is_some());
+    }
+
+    /// Drops the character onto the ground, pressing jump shortly before it lands.
+    /// Returns whether it jumped within the frames after landing.
+    fn jump_before_landing(jump_buffer: Scalar) -> bool {
+        let mut app = app();
+        let character = spawn_character(
+            &mut app,
+            Vector::new(-100.0, STANDING + 60.0),
+            0.0,
+            jump_buffer,
+        );
+
+        while height(&app, character) > STANDING + 25.0 {
+            app.update();
+        }
+        assert!(app.world.get::<Grounded>(character).is_none());
+        send(&mut app, character, MovementAction::Jump);
+
+        (0..10).any(|_| {
+            app.update();
+            jumped(&app, character)
+        })
+    }
+
+    #[test]
+    fn jumps_buffered_before_landing() {
+        assert!(jump_before_landing(0.1));
+    }
+
+    #[test]
+    fn drops_jumps_before_landing_without_buffer() {
+        assert!(!jump_before_landing(0.0));
+    }
+}
@@ -1,15 +1,31 @@
 use std::marker::PhantomData;
+use std::sync::Arc;
 
+use crate::character_controller::{JumpArc, OneWayPlatform};
+use crate::loading::{LevelAssets, TileAssets};
 use crate::GameState;
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
+use bevy::tasks::{block_on, AsyncComputeTaskPool, Task};
+use bevy::utils::{HashMap, HashSet};
 use bevy_xpbd_2d::prelude::*;
-use rand::{thread_rng, Rng};
+use rand::{rngs::StdRng, thread_rng, Rng, SeedableRng};
 
+pub(crate) mod biome;
+pub(crate) mod cave_generator;
 pub(crate) mod chunk;
+pub(crate) mod config;
+pub(crate) mod noise_pipeline;
+pub(crate) mod ore_generator;
 pub(crate) mod perlin_generator;
+pub(crate) mod prefab;
+pub(crate) mod terrain_generator;
 pub(crate) mod tile;
+pub(crate) mod wfc_generator;
 
 use chunk::*;
+use config::*;
+use prefab::{Prefab, Structures};
 use tile::*;
 
 pub const DEFAULT_CHUNK_NEIGHBORS: [(i32, i32); 9] = [
@@ -24,13 +40,202 @@ pub const DEFAULT_CHUNK_NEIGHBORS: [(i32, i32); 9] = [
     (0, 0),
 ];
 
+/// How many generated chunks are spawned per frame at most, the rest waits for the next frames.
+pub const CHUNK_SPAWNS_PER_FRAME: usize = 2;
+
+/// Creates a deterministic rng for a position in the world, so generators can make
+/// random decisions that come out the same no matter which chunk asks first.
+/// The `salt` separates independent decisions made for the same position.
+pub fn seeded_rng(seed: u32, salt: u64, (x, y): (i32, i32)) -> StdRng {
+    // splitmix64 finalizer, mixing in one component at a time
+    let mix = |mut z: u64| {
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    };
+    let hash = [salt, x as u32 as u64, y as u32 as u64]
+        .into_iter()
+        .fold(mix(seed as u64), |acc, v| {
+            mix(acc.wrapping_add(v).wrapping_add(0x9E37_79B9_7F4A_7C15))
+        });
+    StdRng::seed_from_u64(hash)
+}
+
 #[derive(SystemSet, Hash, Debug, Clone, Eq, PartialEq)]
 pub struct ChunkGenerationSet;
 
+/// A marker component for colliders that send characters touching them back to the spawn point.
+#[derive(Component)]
+pub struct Hazard;
+
 #[derive(Resource, Default, Reflect)]
 #[reflect(Resource)]
 pub struct Seed(pub u32);
 
+/// Resets the whole level: all chunks are despawned and generated again, and the player is respawned.
+/// Chunks that are still being generated are dropped, even when the seed stays the same.
+#[derive(Event)]
+pub struct ResetLevel {
+    /// Whether the level gets a new random [`Seed`], or stays the same.
+    pub reseed: bool,
+}
+
+/// The generator chunks are currently generated with.
+///
+/// The generator is created from the [`Seed`], the [`GeneratorConfig`] and the [`Prefab`]s, and
+/// created again whenever one of them changes. Use [`ActiveLevelGenerator::swap`] to switch to a
+/// different generator at runtime, which regenerates all chunks.
+#[derive(Resource)]
+pub struct ActiveLevelGenerator {
+    name: &'static str,
+    create: fn(u32, &GeneratorConfig, Vec<Prefab>) -> Arc<dyn LevelGenerator<Tile = Box<dyn Tile>>>,
+    generator: Option<Arc<dyn LevelGenerator<Tile = Box<dyn Tile>>>>,
+}
+
+impl ActiveLevelGenerator {
+    pub fn new<L: LevelGenerator>() -> Self {
+        Self {
+            name: std::any::type_name::<L>(),
+            create: |seed, config, prefabs| {
+                Arc::new(BoxedTiles {
+                    generator: L::new(seed, config),
+                    structures: Structures::new(seed, prefabs),
+                })
+            },
+            generator: None,
+        }
+    }
+
+    /// Switches to generating chunks with `L`.
+    pub fn swap<L: LevelGenerator>(&mut self) {
+        *self = Self::new::<L>();
+    }
+
+    /// Creates the generator for `seed`, `config` and `prefabs`, replacing the current one.
+    pub fn create_generator(&mut self, seed: u32, config: &GeneratorConfig, prefabs: Vec<Prefab>) {
+        self.generator = Some((self.create)(seed, config, prefabs));
+    }
+
+    /// The type name of the generator.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// The generator, `None` until it has been created for the current seed and config.
+    pub fn generator(&self) -> Option<Arc<dyn LevelGenerator<Tile = Box<dyn Tile>>>> {
+        self.generator.clone()
+    }
+}
+
+/// A chunk generated by a task, ready to be spawned.
+struct GeneratedChunk {
+    chunk: Chunk<Box<dyn Tile>>,
+    colliders: Vec<(Vec2, Collider, TileCollision)>,
+}
+
+/// Chunks that are being generated on the [`AsyncComputeTaskPool`], by chunk position.
+/// Dropping a task cancels it.
+#[derive(Resource, Default)]
+struct PendingChunks(HashMap<(i32, i32), Task<GeneratedChunk>>);
+
+/// The loaded config and prefabs of the level, and whether they changed on disk.
+#[derive(SystemParam)]
+struct GeneratorAssets<'w, 's> {
+    level_assets: Res<'w, LevelAssets>,
+    configs: Res<'w, Assets<GeneratorConfig>>,
+    prefabs: Res<'w, Assets<Prefab>>,
+    config_events: EventReader<'w, 's, AssetEvent<GeneratorConfig>>,
+    prefab_events: EventReader<'w, 's, AssetEvent<Prefab>>,
+}
+
+impl GeneratorAssets<'_, '_> {
+    /// Whether the config or a prefab was modified since the last call.
+    fn modified(&mut self) -> bool {
+        // not short-circuiting, so both readers are drained
+        self.config_events
+            .read()
+            .any(|event| matches!(event, AssetEvent::Modified { .. }))
+            | self
+                .prefab_events
+                .read()
+                .any(|event| matches!(event, AssetEvent::Modified { .. }))
+    }
+}
+
+/// Boxes the tiles of the wrapped generator, so generators with different tile types
+/// can be swapped for each other, and stamps the [`Structures`] into its chunks.
+struct BoxedTiles<L> {
+    generator: L,
+    structures: Structures,
+}
+
+impl<L: LevelGenerator> LevelGenerator for BoxedTiles<L> {
+    type Tile = Box<dyn Tile>;
+    fn new(seed: u32, config: &GeneratorConfig) -> Self {
+        Self {
+            generator: L::new(seed, config),
+            structures: Structures::default(),
+        }
+    }
+
+    fn chunk_size(&self) -> (u32, u32) {
+        self.generator.chunk_size()
+    }
+
+    fn find_spawn_point(&self, near: (i32, i32), area: &SpawnArea) -> Option<(i32, i32)> {
+        let found = self.generator.find_spawn_point(near, area);
+        if self.structures.is_empty() {
+            return found;
+        }
+        // the wrapped generator doesn't know about the structures, so its pick is checked against
+        // the stamped chunks, which are searched themselves if a structure is in the way
+        found
+            .filter(|pos| SpawnSearch::new(self, *pos).fits(*pos, area))
+            .or_else(|| SpawnSearch::new(self, near).closest(near, area))
+    }
+
+    fn generate_chunk(&self, start: (i32, i32)) -> Chunk<Self::Tile> {
+        let chunk = self.generator.generate_chunk(start);
+        let (width, height) = (chunk.width as i32, chunk.height as i32);
+        let mut occupied = HashMap::new();
+        if !self.structures.is_empty() {
+            let positions = chunk
+                .data
+                .iter()
+                .map(|tile| tile.pos())
+                .collect::<HashSet<_>>();
+            occupied.insert(
+                (start.0.div_euclid(width), start.1.div_euclid(height)),
+                positions,
+            );
+        }
+
+        let mut chunk = Chunk {
+            ch_pos: chunk.ch_pos,
+            width: chunk.width,
+            height: chunk.height,
+            data: chunk
+                .data
+                .into_iter()
+                .map(|tile| Box::new(tile) as Box<dyn Tile>)
+                .collect(),
+        };
+        // the placement rules look at the neighbouring chunks as the generator makes them
+        self.structures.stamp(&mut chunk, start, |(x, y)| {
+            let ch_pos = (x.div_euclid(width), y.div_euclid(height));
+            occupied
+                .entry(ch_pos)
+                .or_insert_with(|| {
+                    let start = (ch_pos.0 * width, ch_pos.1 * height);
+                    let chunk = self.generator.generate_chunk(start);
+                    chunk.data.iter().map(|tile| tile.pos()).collect()
+                })
+                .contains(&(x, y))
+        });
+        chunk
+    }
+}
+
 #[derive(Default)]
 pub struct LevelGeneratorPlugin<L: LevelGenerator, F: Component> {
     seed: u32,
@@ -47,9 +252,13 @@ impl<L: LevelGenerator, F: Component> LevelGeneratorPlugin<L, F> {
         }
     }
 
+    /// Starts a generation task for every chunk around the focal points that neither exists
+    /// nor is already being generated.
     fn gen_chunks_around_focal_point(
-        mut commands: Commands,
-        seed: Res<Seed>,
+        active: Res<ActiveLevelGenerator>,
+        level_assets: Res<LevelAssets>,
+        configs: Res<Assets<GeneratorConfig>>,
+        mut pending: ResMut<PendingChunks>,
         focal: Query<&Transform, With<F>>,
         existing_chunks: Query<&ChunkMarker>,
     ) {
@@ -58,58 +267,109 @@ impl<L: LevelGenerator, F: Component> LevelGeneratorPlugin<L, F> {
             return;
         }
 
-        focal.for_each(|t| {
-            let existing_chunks = existing_chunks
-                .iter()
-                .map(|ch| ch.ch_pos)
-                .collect::<Vec<_>>();
+        let (Some(generator), Some(config)) = (
+            active.generator(),
+            configs.get(&level_assets.generator_config),
+        ) else {
+            warn!("Level generator is not ready, skipping chunk generation.");
+            return;
+        };
 
-            let focal_in_chunk_space = to_chunk_space((t.translation.x, t.translation.y));
+        let task_pool = AsyncComputeTaskPool::get();
+        let existing_chunks = existing_chunks
+            .iter()
+            .map(|ch| ch.ch_pos)
+            .collect::<Vec<_>>();
+
+        for t in focal.iter() {
+            let focal_in_chunk_space =
+                to_chunk_space((t.translation.x, t.translation.y), config.chunk_size);
             let (x, y) = focal_in_chunk_space;
 
             for (i, j) in DEFAULT_CHUNK_NEIGHBORS.iter() {
                 let (x, y) = (x + *i, y + *j);
-                if existing_chunks.contains(&(x, y)) {
+                if existing_chunks.contains(&(x, y)) || pending.0.contains_key(&(x, y)) {
                     continue;
                 }
 
-                let start = (x * CHUNK_WIDTH as i32, y * CHUNK_HEIGHT as i32);
-                let chunk = L::generate_chunk(seed.0, start);
-
-                commands
-                    .spawn(ChunkMarker::new(chunk.ch_pos))
-                    .insert(TransformBundle::default())
-                    .insert(VisibilityBundle::default())
-                    .with_children(|child_builder| {
-                        // for each tile in the chunk, spawn a sprite
-                        chunk
-                            .data
-                            .iter()
-                            .for_each(|tile| _ = child_builder.spawn(tile.make_sprite_bundle()));
-
-                        // iterating over the generate_colliders hashmap, because if we change the algorithm for generating colliders,
-                        // we don't have to change this (will not impact performance THAT much anyway)
-                        chunk
-                            .generate_colliders()
-                            .iter()
-                            .for_each(|(pos, collider)| {
-                                child_builder.spawn((
-                                    TransformBundle::from_transform(Transform::from_xyz(
-                                        pos.0 as f32 * TILE_WIDTH,
-                                        pos.1 as f32 * TILE_HEIGHT,
-                                        0.,
-                                    )),
-                                    collider.clone(),
-                                    RigidBody::Static,
-                                ));
-                            });
-                    });
+                let start = (
+                    x * config.chunk_size.0 as i32,
+                    y * config.chunk_size.1 as i32,
+                );
+                let generator = generator.clone();
+                let strategy = config.colliders;
+                let task = task_pool.spawn(async move {
+                    let chunk = generator.generate_chunk(start);
+                    let colliders = chunk.generate_colliders(strategy);
+                    GeneratedChunk { chunk, colliders }
+                });
+                pending.0.insert((x, y), task);
             }
-        });
+        }
+    }
+
+    /// Spawns the chunks whose generation finished, at most [`CHUNK_SPAWNS_PER_FRAME`] per frame.
+    fn spawn_generated_chunks(
+        mut commands: Commands,
+        tile_assets: Res<TileAssets>,
+        mut pending: ResMut<PendingChunks>,
+    ) {
+        let finished = pending
+            .0
+            .iter()
+            .filter(|(_, task)| task.is_finished())
+            .map(|(pos, _)| *pos)
+            .take(CHUNK_SPAWNS_PER_FRAME)
+            .collect::<Vec<_>>();
+
+        for pos in finished {
+            let Some(task) = pending.0.remove(&pos) else {
+                continue;
+            };
+            let GeneratedChunk { chunk, colliders } = block_on(task);
+
+            commands
+                .spawn(ChunkMarker::new(chunk.ch_pos))
+                .insert(TransformBundle::default())
+                .insert(VisibilityBundle::default())
+                .with_children(|child_builder| {
+                    // for each tile in the chunk, spawn a sprite
+                    chunk
+                        .data
+                        .iter()
+                        .for_each(|tile| tile.spawn(child_builder, &tile_assets));
+
+                    // the colliders come with their translation, so this doesn't care about the
+                    // strategy they were generated with
+                    colliders
+                        .into_iter()
+                        .for_each(|(translation, collider, collision)| {
+                            let mut collider = child_builder.spawn((
+                                TransformBundle::from_transform(Transform::from_translation(
+                                    translation.extend(0.),
+                                )),
+                                collider,
+                                RigidBody::Static,
+                            ));
+                            match collision {
+                                TileCollision::Solid => {}
+                                TileCollision::OneWay => {
+                                    collider.insert(OneWayPlatform);
+                                }
+                                TileCollision::Hazard => {
+                                    collider.insert(Hazard);
+                                }
+                            }
+                        });
+                });
+        }
     }
 
     fn despawn_chunks_around_focal_point(
         mut commands: Commands,
+        level_assets: Res<LevelAssets>,
+        configs: Res<Assets<GeneratorConfig>>,
+        mut pending: ResMut<PendingChunks>,
         focal: Query<&Transform, With<F>>,
         existing_chunks: Query<(Entity, &ChunkMarker)>,
     ) {
@@ -118,23 +378,107 @@ impl<L: LevelGenerator, F: Component> LevelGeneratorPlugin<L, F> {
             return;
         }
 
-        focal.for_each(|t| {
-            let focal_in_chunk_space = to_chunk_space((t.translation.x, t.translation.y));
+        let Some(config) = configs.get(&level_assets.generator_config) else {
+            return;
+        };
 
-            let (x, y) = focal_in_chunk_space;
+        let focal_chunks: Vec<(i32, i32)> = focal
+            .iter()
+            .map(|t| to_chunk_space((t.translation.x, t.translation.y), config.chunk_size))
+            .collect();
+        // with several focal points, a chunk is kept as long as any of them is near it
+        let is_near_focal = |(cx, cy): (i32, i32)| {
+            focal_chunks
+                .iter()
+                .any(|(x, y)| DEFAULT_CHUNK_NEIGHBORS.contains(&(x - cx, y - cy)))
+        };
 
-            existing_chunks.for_each(|(ent, chunk)| {
-                let (cx, cy) = chunk.ch_pos;
-                if !DEFAULT_CHUNK_NEIGHBORS.contains(&(x - cx, y - cy)) {
-                    commands.entity(ent).despawn_recursive();
-                }
-            });
+        existing_chunks.for_each(|(ent, chunk)| {
+            if !is_near_focal(chunk.ch_pos) {
+                commands.entity(ent).despawn_recursive();
+            }
+        });
+
+        // chunks that are out of reach before they're done aren't needed anymore either
+        pending.0.retain(|pos, _| is_near_focal(*pos));
+    }
+
+    /// (Re)creates the active generator when it was swapped, or the seed or the config changed.
+    /// A swapped generator or a changed config (on disk) also despawns all chunks,
+    /// so they are generated again. Chunks still being generated by the old generator are dropped.
+    fn update_generator(
+        mut commands: Commands,
+        seed: Res<Seed>,
+        mut assets: GeneratorAssets,
+        mut active: ResMut<ActiveLevelGenerator>,
+        mut pending: ResMut<PendingChunks>,
+        existing_chunks: Query<Entity, With<ChunkMarker>>,
+        jump_arc: Option<Res<JumpArc>>,
+    ) {
+        let config_modified = assets.modified();
+        let Some(config) = assets.configs.get(&assets.level_assets.generator_config) else {
+            return;
+        };
+        let swapped = active.generator.is_none();
+        if config_modified || swapped {
+            existing_chunks.for_each(|ent| commands.entity(ent).despawn_recursive());
+        }
+
+        if config_modified || swapped || seed.is_changed() {
+            info!("Creating level generator {}", active.name);
+            let prefabs = assets
+                .level_assets
+                .prefabs
+                .iter()
+                .filter_map(|prefab| assets.prefabs.get(prefab))
+                .cloned()
+                .collect();
+            let config = GeneratorConfig {
+                jump_arc: jump_arc.map(|arc| *arc),
+                ..config.clone()
+            };
+            active.create_generator(seed.0, &config, prefabs);
+            pending.0.clear();
+        }
+    }
+
+    /// Starts a new level on R or Select.
+    fn reset_level_input(
+        mut reset_event_writer: EventWriter<ResetLevel>,
+        keyboard_input: Res<Input<KeyCode>>,
+        gamepads: Res<Gamepads>,
+        buttons: Res<Input<GamepadButton>>,
+    ) {
+        let select_pressed = gamepads.iter().any(|gamepad| {
+            buttons.just_pressed(GamepadButton {
+                gamepad,
+                button_type: GamepadButtonType::Select,
+            })
         });
+        if keyboard_input.just_pressed(KeyCode::R) || select_pressed {
+            reset_event_writer.send(ResetLevel { reseed: true });
+        }
     }
 
-    fn reset_seed(mut seed: ResMut<Seed>, input: Res<Input<KeyCode>>) {
-        if input.just_pressed(KeyCode::R) {
-            seed.0 = thread_rng().gen();
+    fn reset_level(
+        mut commands: Commands,
+        mut reset_events: EventReader<ResetLevel>,
+        mut seed: ResMut<Seed>,
+        mut pending: ResMut<PendingChunks>,
+        existing_chunks: Query<Entity, With<ChunkMarker>>,
+    ) {
+        let mut reset = false;
+        for event in reset_events.read() {
+            reset = true;
+            if event.reseed {
+                seed.0 = thread_rng().gen();
+            }
+        }
+
+        if reset {
+            info!("Resetting level with seed {}", seed.0);
+            existing_chunks.for_each(|ent| commands.entity(ent).despawn_recursive());
+            pending.0.clear();
         }
     }
 }
@@ -144,21 +488,120 @@ where
     Self: Send + Sync + 'static,
 {
     type Tile: Tile;
-    fn generate_chunk(seed: u32, start: (i32, i32)) -> Chunk<Self::Tile>;
+    /// Creates the generator, this is the place to set up everything that doesn't change between chunks.
+    fn new(seed: u32, config: &GeneratorConfig) -> Self
+    where
+        Self: Sized;
+    /// The width and height of the chunks this generates, in tiles.
+    fn chunk_size(&self) -> (u32, u32);
+    fn generate_chunk(&self, start: (i32, i32)) -> Chunk<Self::Tile>;
+
+    /// Finds the empty space closest to `near` that fits `area` and has ground below it,
+    /// searching the chunk `near` is in and its neighbours. Returns the bottom left tile of the space.
+    ///
+    /// [`TileCollision::Hazard`] tiles aren't ground, and there are none within a tile of the space
+    /// or of the way down to the ground, otherwise the character would be sent back right away.
+    ///
+    /// Generators that know where their safe places are should override this,
+    /// the default generates the chunks around `near` to search them.
+    fn find_spawn_point(&self, near: (i32, i32), area: &SpawnArea) -> Option<(i32, i32)> {
+        SpawnSearch::new(self, near).closest(near, area)
+    }
+}
+
+/// The tiles of the chunk around a position and its neighbours, to search them for spawn points.
+struct SpawnSearch {
+    /// The tiles characters can stand on.
+    solid: HashSet<(i32, i32)>,
+    hazards: HashSet<(i32, i32)>,
+    /// The searched tiles, the first one inclusive and the last one exclusive.
+    bounds: ((i32, i32), (i32, i32)),
+}
+
+impl SpawnSearch {
+    fn new<L: LevelGenerator + ?Sized>(generator: &L, near: (i32, i32)) -> Self {
+        let (width, height) = generator.chunk_size();
+        let (width, height) = (width as i32, height as i32);
+        let ch_pos = (near.0.div_euclid(width), near.1.div_euclid(height));
+
+        let (mut solid, mut hazards) = (HashSet::new(), HashSet::new());
+        for (i, j) in DEFAULT_CHUNK_NEIGHBORS {
+            let start = ((ch_pos.0 + i) * width, (ch_pos.1 + j) * height);
+            for tile in generator.generate_chunk(start).data {
+                match tile.collision() {
+                    TileCollision::Hazard => hazards.insert(tile.pos()),
+                    TileCollision::Solid | TileCollision::OneWay => solid.insert(tile.pos()),
+                };
+            }
+        }
+
+        Self {
+            solid,
+            hazards,
+            bounds: (
+                ((ch_pos.0 - 1) * width, (ch_pos.1 - 1) * height),
+                ((ch_pos.0 + 2) * width, (ch_pos.1 + 2) * height),
+            ),
+        }
+    }
+
+    /// Whether `area` fits at `(x, y)`, see [`LevelGenerator::find_spawn_point`].
+    fn fits(&self, (x, y): (i32, i32), area: &SpawnArea) -> bool {
+        let columns = x..x + area.width;
+        let empty = columns.clone().all(|cx| {
+            (y..y + area.height)
+                .all(|cy| !self.solid.contains(&(cx, cy)) && !self.hazards.contains(&(cx, cy)))
+        });
+        // the highest ground below the space, where the character lands
+        let Some(ground) = (y - area.max_drop..y)
+            .rev()
+            .find(|cy| columns.clone().any(|cx| self.solid.contains(&(cx, *cy))))
+        else {
+            return false;
+        };
+        let safe = (x - 1..=x + area.width)
+            .all(|cx| (ground..=y + area.height).all(|cy| !self.hazards.contains(&(cx, cy))));
+        empty && safe
+    }
+
+    /// The place closest to `near` where `area` fits.
+    fn closest(&self, near: (i32, i32), area: &SpawnArea) -> Option<(i32, i32)> {
+        let ((min_x, min_y), (max_x, max_y)) = self.bounds;
+        let mut candidates = (min_x..max_x - area.width)
+            .flat_map(|x| (min_y + area.max_drop..max_y - area.height).map(move |y| (x, y)))
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|(x, y)| (x - near.0).pow(2) + (y - near.1).pow(2));
+        candidates.into_iter().find(|pos| self.fits(*pos, area))
+    }
+}
+
+/// The space something needs to be spawned, in tiles.
+pub struct SpawnArea {
+    pub width: i32,
+    pub height: i32,
+    /// How far below the space the ground may be at most.
+    pub max_drop: i32,
 }
 
 impl<L: LevelGenerator, F: Component> Plugin for LevelGeneratorPlugin<L, F> {
     fn build(&self, app: &mut App) {
-        app.insert_resource(Seed(self.seed)).add_systems(
-            Update,
-            (
-                Self::reset_seed,
-                Self::gen_chunks_around_focal_point,
-                Self::despawn_chunks_around_focal_point,
-            )
-                .chain()
-                .in_set(ChunkGenerationSet)
-                .run_if(in_state(GameState::Playing)),
-        );
+        app.insert_resource(Seed(self.seed))
+            .insert_resource(ActiveLevelGenerator::new::<L>())
+            .init_resource::<PendingChunks>()
+            .add_event::<ResetLevel>()
+            .add_systems(
+                Update,
+                (
+                    Self::reset_level_input,
+                    Self::reset_level,
+                    Self::update_generator,
+                    Self::gen_chunks_around_focal_point,
+                    Self::spawn_generated_chunks,
+                    Self::despawn_chunks_around_focal_point,
+                )
+                    .chain()
+                    .in_set(ChunkGenerationSet)
+                    .run_if(in_state(GameState::Playing)),
+            );
     }
 }
@@ -0,0 +1,227 @@
+use serde::Deserialize;
+
+use super::noise_pipeline::{Fractal, Noise};
+use super::*;
+
+/// The salt of the biome map's default noise, so it doesn't follow the terrain noise.
+const MAP_SALT: u32 = 100;
+const HAZARD_SALT: u64 = 0;
+
+/// Splits the world into regions with their own [`Biome`], loaded as part of a [`GeneratorConfig`].
+///
+/// Every chunk belongs to the biome the biome map picks at its chunk coordinate. The map is
+/// sampled in chunks rather than tiles, so its features should be a few chunks big.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct BiomeConfig {
+    /// Sampled at the center of every chunk to pick its biome.
+    pub map: Noise,
+    /// How many tiles into its neighbours the density of a biome reaches, so the terrain
+    /// changes gradually. With `0`, biome borders are cut straight along the chunk borders.
+    pub blend: u32,
+    /// Without any biomes, the whole world is one biome made of the config's `layers`.
+    pub biomes: Vec<Biome>,
+}
+
+impl Default for BiomeConfig {
+    fn default() -> Self {
+        Self {
+            map: Noise::perlin(MAP_SALT)
+                .fractal(Fractal {
+                    octaves: 2,
+                    ..default()
+                })
+                .scale(6.0),
+            blend: 4,
+            biomes: Vec::new(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Biome {
+    /// The biome covers the chunks where the biome map is at least this,
+    /// up to where the biome with the next higher `from` starts.
+    #[serde(default = "lowest")]
+    pub from: f64,
+    /// Added to the noise before it is compared to the layers' thresholds,
+    /// higher values fill more of the biome with tiles.
+    #[serde(default)]
+    pub density: f64,
+    /// The thresholds and palette of the biome's tiles.
+    pub layers: Vec<ThresholdLayer>,
+    #[serde(default)]
+    pub hazards: Option<HazardLayer>,
+}
+
+fn lowest() -> f64 {
+    f64::NEG_INFINITY
+}
+
+impl Biome {
+    /// Whether any layer has a tile where the noise is `value`.
+    pub fn filled(&self, value: f64) -> bool {
+        self.layers.iter().any(|layer| value > layer.threshold)
+    }
+}
+
+/// Hazard tiles lying on top of the ground, see [`Hazard`].
+#[derive(Deserialize, Debug, Clone)]
+pub struct HazardLayer {
+    /// The chance of a hazard on every tile of the ground's surface.
+    pub chance: f64,
+    /// Hex colour, used by [`ColorTile`]s.
+    pub color: String,
+    /// Index into the tile atlas, used by [`TexturedTile`]s.
+    pub tex_index: usize,
+    /// The parsed colour.
+    #[serde(skip)]
+    pub parsed_color: Color,
+}
+
+/// Picks the [`Biome`] of every chunk from a [`BiomeConfig`].
+pub struct Biomes {
+    seed: u32,
+    chunk_size: (i32, i32),
+    map: Noise,
+    blend: i32,
+    /// Sorted by [`Biome::from`].
+    biomes: Vec<Biome>,
+}
+
+impl Biomes {
+    pub fn new(seed: u32, config: &GeneratorConfig) -> Self {
+        let (width, height) = (config.chunk_size.0 as i32, config.chunk_size.1 as i32);
+        let mut biomes = config.biomes.biomes.clone();
+        if biomes.is_empty() {
+            biomes.push(Biome {
+                from: lowest(),
+                density: 0.0,
+                layers: config.layers.clone(),
+                hazards: None,
+            });
+        }
+        biomes.sort_by(|a, b| a.from.total_cmp(&b.from));
+
+        Self {
+            seed,
+            chunk_size: (width, height),
+            map: config.biomes.map.clone().seeded(seed),
+            // blending further than into the direct neighbours would need a wider search
+            blend: (config.biomes.blend as i32).min(width).min(height),
+            biomes,
+        }
+    }
+
+    fn chunk_biome(&self, (x, y): (i32, i32)) -> &Biome {
+        let value = self.map.sample([x as f64 + 0.5, y as f64 + 0.5]);
+        self.biomes
+            .iter()
+            .rev()
+            .find(|biome| value >= biome.from)
+            .unwrap_or(&self.biomes[0])
+    }
+
+    /// The biome of the chunk the tile at `pos` lies in.
+    pub fn biome(&self, (x, y): (i32, i32)) -> &Biome {
+        let (width, height) = self.chunk_size;
+        self.chunk_biome((x.div_euclid(width), y.div_euclid(height)))
+    }
+
+    /// The [`Biome::density`] at `pos`, blended with the neighbouring chunks' biomes within
+    /// [`BiomeConfig::blend`] tiles of the chunk borders.
+    pub fn density(&self, (x, y): (i32, i32)) -> f64 {
+        let (width, height) = self.chunk_size;
+        let ch_pos = (x.div_euclid(width), y.div_euclid(height));
+        if self.blend == 0 {
+            return self.chunk_biome(ch_pos).density;
+        }
+
+        // falls off linearly with the distance to the chunk, in tiles
+        let weight = |pos: i32, start: i32, len: i32| {
+            let distance = (start - pos).max(pos - (start + len - 1)).max(0);
+            (1.0 - distance as f64 / (self.blend + 1) as f64).max(0.0)
+        };
+        let (mut density, mut total) = (0.0, 0.0);
+        for (dx, dy) in DEFAULT_CHUNK_NEIGHBORS {
+            let (cx, cy) = (ch_pos.0 + dx, ch_pos.1 + dy);
+            let weight = weight(x, cx * width, width) * weight(y, cy * height, height);
+            if weight > 0.0 {
+                density += weight * self.chunk_biome((cx, cy)).density;
+                total += weight;
+            }
+        }
+        density / total
+    }
+
+    /// The hazards at `pos`, if the biome there has any and rolled one.
+    /// Whether `pos` is on top of the ground is up to the generator.
+    pub fn hazard(&self, pos: (i32, i32)) -> Option<&HazardLayer> {
+        let hazards = self.biome(pos).hazards.as_ref()?;
+        seeded_rng(self.seed, HAZARD_SALT, pos)
+            .gen_bool(hazards.chance.clamp(0.0, 1.0))
+            .then_some(hazards)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_blend(blend: u32) -> Biomes {
+        let mut config = GeneratorConfigLoader::parse(
+            b"(
+                chunk_size: (16, 16),
+                layers: [],
+                biomes: (
+                    map: Scale(Perlin(salt: 0), 2.0),
+                    biomes: [
+                        (density: -0.5, layers: [(threshold: 0.0, color: \"#FFFFFF\", tex_index: 0, z_index: 0)]),
+                        (from: 0.0, density: 0.5, layers: [(threshold: 0.0, color: \"#000000\", tex_index: 1, z_index: 0)]),
+                    ],
+                ),
+            )",
+        )
+        .unwrap();
+        config.biomes.blend = blend;
+        Biomes::new(5, &config)
+    }
+
+    /// Two horizontally neighbouring chunks in different biomes.
+    fn border(biomes: &Biomes) -> i32 {
+        (-50..50)
+            .find(|x| biomes.chunk_biome((*x, 0)).density != biomes.chunk_biome((x + 1, 0)).density)
+            .expect("no biome border found")
+    }
+
+    #[test]
+    fn biomes_are_picked_per_chunk() {
+        let biomes = with_blend(4);
+        let x = border(&biomes) * 16;
+        let left = biomes.biome((x, 3)).layers[0].parsed_color;
+        assert_eq!(biomes.biome((x + 15, 7)).layers[0].parsed_color, left);
+        assert_ne!(biomes.biome((x + 16, 7)).layers[0].parsed_color, left);
+    }
+
+    #[test]
+    fn density_blends_across_borders() {
+        let biomes = with_blend(4);
+        // the last tile of the left chunk, in the middle of its height
+        let (x, y) = (border(&biomes) * 16 + 15, 8);
+        let steps = (x - 6..x + 8)
+            .map(|x| biomes.density((x, y)))
+            .collect::<Vec<_>>();
+        // far from the border, the chunks' own densities
+        assert_eq!(steps[0].abs(), 0.5);
+        assert_eq!(steps[13].abs(), 0.5);
+        // and never a jump of more than a blend step in between
+        assert!(
+            steps.windows(2).all(|w| (w[1] - w[0]).abs() < 0.25),
+            "{steps:?}"
+        );
+
+        let hard = with_blend(0);
+        let (x, y) = (border(&hard) * 16 + 15, 8);
+        assert_eq!((hard.density((x, y)) - hard.density((x + 1, y))).abs(), 1.0);
+    }
+}
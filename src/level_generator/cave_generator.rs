@@ -0,0 +1,291 @@
+use std::collections::VecDeque;
+
+use super::*;
+
+const ROCK_COLOR: &str = "#4B3D44";
+
+/// Chunk anchors keep this far from the chunk border, in tiles.
+const ANCHOR_MARGIN: i32 = 3;
+/// The radius of the room carved around every anchor, in tiles.
+const ROOM_RADIUS: i32 = 2;
+
+const FILL_SALT: u64 = 0;
+const ANCHOR_SALT: u64 = 1;
+const TUNNEL_SALT: u64 = 2;
+
+/// Generates caves with a cellular automaton: every tile starts out as rock by chance,
+/// then the rock is smoothed a few times, each tile becoming rock if most of its neighbours are.
+///
+/// The random fill only depends on the seed and the tile position, and after `n` smoothing passes
+/// a tile only depends on the fill up to `n` tiles around it. Every chunk simulates its own area
+/// plus that margin, so neighbouring chunks agree on their borders without knowing about each other.
+///
+/// To keep the caves connected, every chunk has an anchor room, which only depends on the seed and
+/// the chunk position, and tunnels connect the anchors of neighbouring chunks. A flood fill then
+/// finds the pockets the automaton left isolated within the chunk and carves a tunnel from each of
+/// them to the anchor. Pockets too small to matter are filled with rock instead, unless they
+/// touch the chunk border and might continue in the neighbouring chunk.
+pub struct CaveLevelGenerator {
+    seed: u32,
+    chunk_size: (u32, u32),
+    caves: CaveConfig,
+    color: Color,
+}
+
+impl CaveLevelGenerator {
+    /// The tile in the chunk at `ch_pos` all of its tunnels start from.
+    fn anchor(&self, ch_pos: (i32, i32)) -> (i32, i32) {
+        let (width, height) = (self.chunk_size.0 as i32, self.chunk_size.1 as i32);
+        let mut rng = seeded_rng(self.seed, ANCHOR_SALT, ch_pos);
+        let margin = ANCHOR_MARGIN.min(width / 2).min(height / 2);
+        (
+            ch_pos.0 * width + rng.gen_range(margin..width - margin),
+            ch_pos.1 * height + rng.gen_range(margin..height - margin),
+        )
+    }
+
+    /// The center line of the tunnel from the anchor of the chunk at `ch_pos`
+    /// to the anchor of its neighbour in direction `dir`.
+    fn tunnel(&self, ch_pos: (i32, i32), dir: (i32, i32)) -> Vec<(i32, i32)> {
+        let from = self.anchor(ch_pos);
+        let to = self.anchor((ch_pos.0 + dir.0, ch_pos.1 + dir.1));
+        let horizontal_first = seeded_rng(self.seed, TUNNEL_SALT, ch_pos).gen_bool(0.5);
+        l_path(from, to, horizontal_first)
+    }
+
+    /// Whether every tile of the area starting at `start` is rock, after the random fill and smoothing.
+    /// `grid[x + y * width]`.
+    fn smoothed_rock(
+        &self,
+        (start_x, start_y): (i32, i32),
+        (width, height): (i32, i32),
+    ) -> Vec<bool> {
+        let margin = self.caves.smoothing as i32;
+        let (full_width, full_height) = (width + 2 * margin, height + 2 * margin);
+        let mut rock = (0..full_height)
+            .flat_map(|y| (0..full_width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let pos = (start_x - margin + x, start_y - margin + y);
+                seeded_rng(self.seed, FILL_SALT, pos).gen_bool(self.caves.fill)
+            })
+            .collect::<Vec<_>>();
+
+        // every pass leaves the outermost ring of the area that is still correct behind
+        for pass in 0..margin {
+            let previous = rock.clone();
+            for y in pass + 1..full_height - pass - 1 {
+                for x in pass + 1..full_width - pass - 1 {
+                    let neighbours = (-1..=1)
+                        .flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+                        .filter(|(dx, dy)| {
+                            (*dx, *dy) != (0, 0)
+                                && previous[((x + dx) + (y + dy) * full_width) as usize]
+                        })
+                        .count();
+                    let cell = &mut rock[(x + y * full_width) as usize];
+                    if neighbours > 4 {
+                        *cell = true;
+                    } else if neighbours < 4 {
+                        *cell = false;
+                    }
+                }
+            }
+        }
+
+        (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| rock[((x + margin) + (y + margin) * full_width) as usize])
+            .collect()
+    }
+}
+
+/// The tiles on an L shaped path between two tiles, turning once.
+fn l_path(from: (i32, i32), to: (i32, i32), horizontal_first: bool) -> Vec<(i32, i32)> {
+    let corner = if horizontal_first {
+        (to.0, from.1)
+    } else {
+        (from.0, to.1)
+    };
+    let line = |(ax, ay): (i32, i32), (bx, by): (i32, i32)| {
+        let (dx, dy) = ((bx - ax).signum(), (by - ay).signum());
+        let len = (bx - ax).abs().max((by - ay).abs());
+        (0..=len).map(move |i| (ax + dx * i, ay + dy * i))
+    };
+    line(from, corner).chain(line(corner, to)).collect()
+}
+
+impl LevelGenerator for CaveLevelGenerator {
+    type Tile = ColorTile;
+    fn new(seed: u32, config: &GeneratorConfig) -> Self {
+        Self {
+            seed,
+            chunk_size: config.chunk_size,
+            caves: config.caves.clone(),
+            color: Color::hex(ROCK_COLOR).expect("could not parse tile color"),
+        }
+    }
+
+    fn chunk_size(&self) -> (u32, u32) {
+        self.chunk_size
+    }
+
+    fn generate_chunk(&self, (start_x, start_y): (i32, i32)) -> Chunk<Self::Tile> {
+        let (width, height) = (self.chunk_size.0 as i32, self.chunk_size.1 as i32);
+        let ch_pos = (start_x.div_euclid(width), start_y.div_euclid(height));
+        let index = |(x, y): (i32, i32)| {
+            let (x, y) = (x - start_x, y - start_y);
+            ((0..width).contains(&x) && (0..height).contains(&y)).then(|| (x + y * width) as usize)
+        };
+
+        let mut rock = self.smoothed_rock((start_x, start_y), (width, height));
+        let carve = |rock: &mut Vec<bool>, center: (i32, i32), radius: i32| {
+            for dx in -radius..=radius {
+                for dy in -radius..=radius {
+                    if let Some(i) = index((center.0 + dx, center.1 + dy)) {
+                        rock[i] = false;
+                    }
+                }
+            }
+        };
+        // the open tiles connected to `start`
+        let flood = |rock: &Vec<bool>, seen: &mut Vec<bool>, start: usize| {
+            seen[start] = true;
+            let mut open = Vec::new();
+            let mut queue = VecDeque::from([start]);
+            while let Some(i) = queue.pop_front() {
+                let pos = (start_x + i as i32 % width, start_y + i as i32 / width);
+                open.push(pos);
+                for (dx, dy) in [(1, 0), (0, 1), (-1, 0), (0, -1)] {
+                    match index((pos.0 + dx, pos.1 + dy)) {
+                        Some(next) if !rock[next] && !seen[next] => {
+                            seen[next] = true;
+                            queue.push_back(next);
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            open
+        };
+
+        // the anchor room, and the tunnels to the four neighbours, as far as they run through this chunk
+        let anchor = self.anchor(ch_pos);
+        carve(&mut rock, anchor, ROOM_RADIUS);
+        let tunnels = [
+            (ch_pos, (1, 0)),
+            (ch_pos, (0, 1)),
+            ((ch_pos.0 - 1, ch_pos.1), (1, 0)),
+            ((ch_pos.0, ch_pos.1 - 1), (0, 1)),
+        ];
+        for (from, dir) in tunnels {
+            for pos in self.tunnel(from, dir) {
+                carve(&mut rock, pos, self.caves.tunnel_radius);
+            }
+        }
+
+        // flood fill the open tiles to find the pockets that aren't connected to the anchor
+        let mut seen = vec![false; rock.len()];
+        flood(&rock, &mut seen, index(anchor).unwrap());
+        let mut pockets = Vec::new();
+        for start in 0..rock.len() {
+            if !rock[start] && !seen[start] {
+                pockets.push(flood(&rock, &mut seen, start));
+            }
+        }
+
+        // small pockets are filled before any tunnels are carved, which might run through them
+        let (small, pockets): (Vec<_>, Vec<_>) = pockets.into_iter().partition(|pocket| {
+            let touches_border = pocket.iter().any(|(x, y)| {
+                *x == start_x
+                    || *y == start_y
+                    || *x == start_x + width - 1
+                    || *y == start_y + height - 1
+            });
+            pocket.len() < self.caves.min_pocket && !touches_border
+        });
+        for pos in small.into_iter().flatten() {
+            rock[index(pos).unwrap()] = true;
+        }
+
+        for pocket in pockets {
+            let closest = pocket
+                .iter()
+                .copied()
+                .min_by_key(|(x, y)| (x - anchor.0).pow(2) + (y - anchor.1).pow(2))
+                .unwrap();
+            let horizontal_first = seeded_rng(self.seed, TUNNEL_SALT, closest).gen_bool(0.5);
+            for pos in l_path(closest, anchor, horizontal_first) {
+                carve(&mut rock, pos, self.caves.tunnel_radius);
+            }
+        }
+
+        let data = rock
+            .iter()
+            .enumerate()
+            .filter(|(_, rock)| **rock)
+            .map(|(i, _)| {
+                let pos = (start_x + i as i32 % width, start_y + i as i32 / width);
+                ColorTile::new(pos, self.color, 0)
+            })
+            .collect();
+
+        Chunk {
+            ch_pos,
+            width: width as u32,
+            height: height as u32,
+            data,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generator(seed: u32) -> CaveLevelGenerator {
+        let config = GeneratorConfigLoader::parse(b"(chunk_size: (16, 16), layers: [])").unwrap();
+        CaveLevelGenerator::new(seed, &config)
+    }
+
+    /// The rock of the 3x3 chunks around the origin.
+    fn rock(generator: &CaveLevelGenerator) -> HashSet<(i32, i32)> {
+        DEFAULT_CHUNK_NEIGHBORS
+            .iter()
+            .flat_map(|(i, j)| generator.generate_chunk((i * 16, j * 16)).data)
+            .map(|tile| tile.pos())
+            .collect()
+    }
+
+    #[test]
+    fn caves_are_connected_across_chunks() {
+        for seed in 0..10 {
+            let generator = generator(seed);
+            let rock = rock(&generator);
+            let inside = |(x, y): (i32, i32)| (-16..32).contains(&x) && (-16..32).contains(&y);
+            let open = (-16..32)
+                .flat_map(|x| (-16..32).map(move |y| (x, y)))
+                .filter(|pos| !rock.contains(pos))
+                .count();
+
+            let anchor = generator.anchor((0, 0));
+            let mut reached = HashSet::from([anchor]);
+            let mut queue = VecDeque::from([anchor]);
+            while let Some((x, y)) = queue.pop_front() {
+                for next in [(x + 1, y), (x, y + 1), (x - 1, y), (x, y - 1)] {
+                    if inside(next) && !rock.contains(&next) && reached.insert(next) {
+                        queue.push_back(next);
+                    }
+                }
+            }
+            assert_eq!(reached.len(), open, "seed {seed}");
+        }
+    }
+
+    #[test]
+    fn chunks_come_out_the_same_every_time() {
+        let generator = generator(3);
+        let chunk = generator.generate_chunk((16, -32));
+        assert_eq!(generator.generate_chunk((16, -32)), chunk);
+        assert!(!chunk.data.is_empty());
+    }
+}
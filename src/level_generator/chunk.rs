@@ -1,10 +1,7 @@
-use bevy::{prelude::*, utils::HashMap};
+use bevy::{prelude::*, utils::HashSet};
 use bevy_xpbd_2d::components::Collider;
 
-use super::{Tile, TILE_HEIGHT, TILE_WIDTH};
-
-pub const CHUNK_WIDTH: f32 = 16.0;
-pub const CHUNK_HEIGHT: f32 = 16.0;
+use super::{ColliderStrategy, Tile, TileCollision, TILE_HEIGHT, TILE_WIDTH};
 
 #[derive(Debug, Eq, PartialEq, Default)]
 pub struct Chunk<T: Tile> {
@@ -15,20 +12,166 @@ pub struct Chunk<T: Tile> {
 }
 
 impl<T: Tile> Chunk<T> {
-    // TODO: make this more efficient?
-    pub fn generate_colliders(&self) -> HashMap<(i32, i32), Collider> {
-        let mut colliders = HashMap::new();
-        for tile in &self.data {
-            colliders.insert(tile.pos(), Collider::cuboid(TILE_WIDTH, TILE_HEIGHT));
+    /// Creates the colliders of all tiles in the chunk, together with their translation
+    /// relative to the chunk and how characters collide with them. Stacked tiles share a collider,
+    /// which is a hazard if any of them is, and otherwise solid if any of them is.
+    ///
+    /// Tiles generated past the chunk's edges get their colliders from the chunk they lie in,
+    /// so neighbouring chunks don't build overlapping colliders.
+    pub fn generate_colliders(
+        &self,
+        strategy: ColliderStrategy,
+    ) -> Vec<(Vec2, Collider, TileCollision)> {
+        let (start_x, start_y) = (
+            self.ch_pos.0 * self.width as i32,
+            self.ch_pos.1 * self.height as i32,
+        );
+        let inside = |(x, y): (i32, i32)| {
+            (start_x..start_x + self.width as i32).contains(&x)
+                && (start_y..start_y + self.height as i32).contains(&y)
+        };
+        let (mut solid, mut one_way, mut hazard) = (HashSet::new(), HashSet::new(), HashSet::new());
+        for tile in self.data.iter().filter(|tile| inside(tile.pos())) {
+            match tile.collision() {
+                TileCollision::Solid => solid.insert(tile.pos()),
+                TileCollision::OneWay => one_way.insert(tile.pos()),
+                TileCollision::Hazard => hazard.insert(tile.pos()),
+            };
         }
-        colliders
+        solid.retain(|pos| !hazard.contains(pos));
+        one_way.retain(|pos| !solid.contains(pos) && !hazard.contains(pos));
+
+        let (solid_rects, one_way_rects, hazard_rects) = match strategy {
+            ColliderStrategy::PerTile => (
+                single_tiles(&solid),
+                single_tiles(&one_way),
+                single_tiles(&hazard),
+            ),
+            // one-way platforms are only merged along rows, so every collider is a surface to land on
+            _ => (
+                merge_rectangles(&solid),
+                merge_rows(&one_way),
+                merge_rectangles(&hazard),
+            ),
+        };
+        let solid = colliders(&solid_rects, strategy)
+            .into_iter()
+            .map(|(translation, collider)| (translation, collider, TileCollision::Solid));
+        let one_way = colliders(&one_way_rects, strategy)
+            .into_iter()
+            .map(|(translation, collider)| (translation, collider, TileCollision::OneWay));
+        let hazard = colliders(&hazard_rects, strategy)
+            .into_iter()
+            .map(|(translation, collider)| (translation, collider, TileCollision::Hazard));
+        solid.chain(one_way).chain(hazard).collect()
+    }
+}
+
+fn single_tiles(tiles: &HashSet<(i32, i32)>) -> Vec<TileRect> {
+    tiles.iter().map(|pos| TileRect::new(*pos, 1, 1)).collect()
+}
+
+/// The colliders of `rects`, a single compound collider for [`ColliderStrategy::Compound`].
+fn colliders(rects: &[TileRect], strategy: ColliderStrategy) -> Vec<(Vec2, Collider)> {
+    match strategy {
+        ColliderStrategy::PerTile | ColliderStrategy::Rectangles => {
+            rects.iter().map(TileRect::collider).collect()
+        }
+        ColliderStrategy::Compound => {
+            if rects.is_empty() {
+                return Vec::new();
+            }
+            let shapes = rects
+                .iter()
+                .map(|rect| {
+                    let (translation, collider) = rect.collider();
+                    (translation, 0.0, collider)
+                })
+                .collect();
+            vec![(Vec2::ZERO, Collider::compound(shapes))]
+        }
+    }
+}
+
+/// A rectangle of tiles, `pos` being its bottom left tile.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TileRect {
+    pub pos: (i32, i32),
+    pub width: i32,
+    pub height: i32,
+}
+
+impl TileRect {
+    pub fn new(pos: (i32, i32), width: i32, height: i32) -> Self {
+        Self { pos, width, height }
+    }
+
+    /// A collider covering the rectangle, with the translation of its center.
+    /// Tiles are centered on their position, so that's where the rectangle's corners are offset from.
+    fn collider(&self) -> (Vec2, Collider) {
+        let center = Vec2::new(
+            (self.pos.0 as f32 + (self.width - 1) as f32 / 2.0) * TILE_WIDTH,
+            (self.pos.1 as f32 + (self.height - 1) as f32 / 2.0) * TILE_HEIGHT,
+        );
+        let collider = Collider::cuboid(
+            self.width as f32 * TILE_WIDTH,
+            self.height as f32 * TILE_HEIGHT,
+        );
+        (center, collider)
     }
 }
 
-pub fn to_chunk_space((x, y): (f32, f32)) -> (i32, i32) {
+/// Greedily covers `tiles` with non-overlapping rectangles: starting at the lowest, leftmost
+/// tile that isn't covered yet, a rectangle is grown to the right as far as possible,
+/// then upwards as long as the whole row below it continues.
+pub fn merge_rectangles(tiles: &HashSet<(i32, i32)>) -> Vec<TileRect> {
+    let mut sorted = tiles.iter().copied().collect::<Vec<_>>();
+    sorted.sort_by_key(|(x, y)| (*y, *x));
+
+    let mut covered = HashSet::new();
+    let mut rects = Vec::new();
+    let free = |pos: (i32, i32), covered: &HashSet<(i32, i32)>| {
+        tiles.contains(&pos) && !covered.contains(&pos)
+    };
+    for (x, y) in sorted {
+        if covered.contains(&(x, y)) {
+            continue;
+        }
+
+        let mut width = 1;
+        while free((x + width, y), &covered) {
+            width += 1;
+        }
+        let mut height = 1;
+        while (x..x + width).all(|col| free((col, y + height), &covered)) {
+            height += 1;
+        }
+
+        covered.extend((x..x + width).flat_map(|col| (y..y + height).map(move |row| (col, row))));
+        rects.push(TileRect::new((x, y), width, height));
+    }
+    rects
+}
+
+/// Covers `tiles` with rectangles one tile high, each as wide as possible.
+pub fn merge_rows(tiles: &HashSet<(i32, i32)>) -> Vec<TileRect> {
+    let mut sorted = tiles.iter().copied().collect::<Vec<_>>();
+    sorted.sort_by_key(|(x, y)| (*y, *x));
+
+    let mut rects: Vec<TileRect> = Vec::new();
+    for (x, y) in sorted {
+        match rects.last_mut() {
+            Some(rect) if rect.pos.1 == y && rect.pos.0 + rect.width == x => rect.width += 1,
+            _ => rects.push(TileRect::new((x, y), 1, 1)),
+        }
+    }
+    rects
+}
+
+pub fn to_chunk_space((x, y): (f32, f32), (width, height): (u32, u32)) -> (i32, i32) {
     (
-        (x / CHUNK_WIDTH / TILE_WIDTH).floor() as i32,
-        (y / CHUNK_HEIGHT / TILE_HEIGHT).floor() as i32,
+        (x / width as f32 / TILE_WIDTH).floor() as i32,
+        (y / height as f32 / TILE_HEIGHT).floor() as i32,
     )
 }
 
@@ -42,3 +185,166 @@ impl ChunkMarker {
         Self { ch_pos }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+    use crate::level_generator::{seeded_rng, ColorTile};
+
+    fn contains(rect: &TileRect, (x, y): (i32, i32)) -> bool {
+        (rect.pos.0..rect.pos.0 + rect.width).contains(&x)
+            && (rect.pos.1..rect.pos.1 + rect.height).contains(&y)
+    }
+
+    /// Random tiles of the chunk at the origin, and of the tiles right past its edges.
+    fn random_tiles(seed: u32, density: f64) -> HashSet<(i32, i32)> {
+        let mut rng = seeded_rng(seed, 0, (0, 0));
+        (-1..17)
+            .flat_map(|x| (-1..17).map(move |y| (x, y)))
+            .filter(|_| rng.gen_bool(density))
+            .collect()
+    }
+
+    fn inside((x, y): (i32, i32)) -> bool {
+        (0..16).contains(&x) && (0..16).contains(&y)
+    }
+
+    fn chunk(tiles: &HashSet<(i32, i32)>) -> Chunk<ColorTile> {
+        Chunk {
+            ch_pos: (0, 0),
+            width: 16,
+            height: 16,
+            // stacked layers put several tiles on the same position
+            data: tiles
+                .iter()
+                .flat_map(|pos| [0, 1].map(|z| ColorTile::new(*pos, Color::WHITE, z)))
+                .collect(),
+        }
+    }
+
+    fn collider_area(colliders: &[(Vec2, Collider, TileCollision)]) -> f32 {
+        colliders
+            .iter()
+            .map(|(_, collider, _)| collider.mass_properties(1.0).mass())
+            .sum()
+    }
+
+    #[test]
+    fn rectangles_cover_every_tile_exactly_once() {
+        for seed in 0..20 {
+            let tiles = random_tiles(seed, 0.6);
+            let rects = merge_rectangles(&tiles);
+
+            let area: i32 = rects.iter().map(|rect| rect.width * rect.height).sum();
+            assert_eq!(area as usize, tiles.len());
+            for tile in &tiles {
+                assert_eq!(rects.iter().filter(|rect| contains(rect, *tile)).count(), 1);
+            }
+        }
+    }
+
+    #[test]
+    fn rectangles_merge_solid_blocks() {
+        let tiles = (0..16)
+            .flat_map(|x| (0..4).map(move |y| (x, y)))
+            .collect::<HashSet<_>>();
+        assert_eq!(merge_rectangles(&tiles), vec![TileRect::new((0, 0), 16, 4)]);
+    }
+
+    #[test]
+    fn merged_collider_area_equals_tile_area() {
+        let tile_area = TILE_WIDTH * TILE_HEIGHT;
+        for seed in 0..20 {
+            let tiles = random_tiles(seed, 0.4);
+            let chunk = chunk(&tiles);
+            let expected = tiles.iter().filter(|pos| inside(**pos)).count() as f32 * tile_area;
+
+            for strategy in [
+                ColliderStrategy::PerTile,
+                ColliderStrategy::Rectangles,
+                ColliderStrategy::Compound,
+            ] {
+                let area = collider_area(&chunk.generate_colliders(strategy));
+                assert!(
+                    (area - expected).abs() < tile_area * 0.01,
+                    "{strategy:?}: {area} != {expected}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn merging_reduces_collider_count() {
+        let tiles = random_tiles(7, 0.8);
+        let chunk = chunk(&tiles);
+        let per_tile = chunk.generate_colliders(ColliderStrategy::PerTile).len();
+        let merged = chunk.generate_colliders(ColliderStrategy::Rectangles).len();
+        assert_eq!(per_tile, tiles.iter().filter(|pos| inside(**pos)).count());
+        assert!(merged < per_tile);
+        assert_eq!(
+            chunk.generate_colliders(ColliderStrategy::Compound).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn neighbouring_chunks_dont_share_colliders() {
+        // the tiles of the chunk right of the origin, and of the tiles right past its edges
+        let right = random_tiles(3, 0.5)
+            .into_iter()
+            .map(|(x, y)| (x + 16, y))
+            .collect::<HashSet<_>>();
+        let mut right = chunk(&right);
+        right.ch_pos = (1, 0);
+        let left = chunk(&random_tiles(4, 0.5));
+
+        let centers = |chunk: &Chunk<ColorTile>| {
+            chunk
+                .generate_colliders(ColliderStrategy::PerTile)
+                .into_iter()
+                .map(|(center, ..)| (center.x as i32, center.y as i32))
+                .collect::<HashSet<_>>()
+        };
+        assert!(centers(&left).is_disjoint(&centers(&right)));
+    }
+
+    #[test]
+    fn one_way_platforms_get_their_own_colliders() {
+        let mut chunk = chunk(&(0..4).map(|x| (x, 0)).collect());
+        chunk.data.extend(
+            (0..4).flat_map(|x| [(x, 3), (x, 4)]).map(|pos| {
+                ColorTile::new(pos, Color::WHITE, 0).with_collision(TileCollision::OneWay)
+            }),
+        );
+        // solid wins over one-way on the same position
+        chunk
+            .data
+            .push(ColorTile::new((0, 0), Color::WHITE, 2).with_collision(TileCollision::OneWay));
+
+        let colliders = chunk.generate_colliders(ColliderStrategy::Rectangles);
+        let one_way = colliders
+            .iter()
+            .filter(|(_, _, collision)| *collision == TileCollision::OneWay)
+            .count();
+        assert_eq!(colliders.len(), 3);
+        assert_eq!(one_way, 2);
+    }
+
+    #[test]
+    fn hazards_win_over_stacked_tiles() {
+        let mut chunk = chunk(&(0..4).map(|x| (x, 0)).collect());
+        chunk.data.extend([
+            ColorTile::new((1, 0), Color::RED, 1).with_collision(TileCollision::Hazard),
+            ColorTile::new((1, 0), Color::WHITE, 2).with_collision(TileCollision::OneWay),
+        ]);
+
+        let colliders = chunk.generate_colliders(ColliderStrategy::Rectangles);
+        let count = |collision| colliders.iter().filter(|(_, _, c)| *c == collision).count();
+        // the hazard splits the row of solid tiles
+        assert_eq!(count(TileCollision::Solid), 2);
+        assert_eq!(count(TileCollision::Hazard), 1);
+        assert_eq!(count(TileCollision::OneWay), 0);
+    }
+}
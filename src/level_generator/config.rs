@@ -0,0 +1,325 @@
+use bevy::asset::AsyncReadExt;
+use bevy::prelude::*;
+use bevy::render::color::HexColorError;
+use bevy::utils::thiserror;
+use macros::auto_ron_asset_loader;
+use serde::Deserialize;
+
+use super::biome::BiomeConfig;
+use super::noise_pipeline::{Fractal, Noise};
+use super::ore_generator::OreLevelGenerator;
+use super::wfc_generator::WfcLevelGenerator;
+use super::TileCollision;
+use crate::character_controller::JumpArc;
+
+/// The `noise_scale` of configs that don't set one.
+const NOISE_SCALE: f64 = 12.5;
+
+/// Configuration of the level generation, loaded from `*.generator.ron` files.
+///
+/// With the `dev` feature the file is watched, and every change regenerates the level.
+/// A change that doesn't load keeps the previous config.
+#[auto_ron_asset_loader(extensions = ["generator.ron"], validate = GeneratorConfig::validate)]
+#[derive(Deserialize, Debug, Clone)]
+pub struct GeneratorConfig {
+    /// The width and height of a chunk in tiles.
+    pub chunk_size: (u32, u32),
+    /// The noise the layers' thresholds are compared against. Without it, `octaves` octaves of
+    /// Perlin noise are scaled by `noise_scale`.
+    #[serde(default)]
+    pub noise: Option<Noise>,
+    /// Deprecated in favour of `noise`, and can't be combined with it.
+    /// Tile positions are divided by this before sampling noise, bigger values make bigger features.
+    #[serde(default)]
+    pub noise_scale: Option<f64>,
+    /// Deprecated in favour of `noise`, and can't be combined with it.
+    /// How many octaves of noise are summed up, each adding finer detail.
+    #[serde(default)]
+    pub octaves: Option<u32>,
+    /// A tile is stacked at a position for every layer whose threshold the noise exceeds there.
+    /// Only used without `biomes`, and can't be combined with them.
+    #[serde(default)]
+    pub layers: Vec<ThresholdLayer>,
+    /// Regions of the world with their own layers, used by the Perlin generators.
+    #[serde(default)]
+    pub biomes: BiomeConfig,
+    /// How the colliders of a chunk are built from its tiles.
+    #[serde(default)]
+    pub colliders: ColliderStrategy,
+    /// Used by the [`CaveLevelGenerator`](super::cave_generator::CaveLevelGenerator).
+    #[serde(default)]
+    pub caves: CaveConfig,
+    /// Used by the [`TerrainLevelGenerator`](super::terrain_generator::TerrainLevelGenerator).
+    #[serde(default)]
+    pub terrain: TerrainConfig,
+    /// What the player can reach with a single jump, taken from the [`JumpArc`] resource when
+    /// the generator is created. Needed by the
+    /// [`OreLevelGenerator`](super::ore_generator::OreLevelGenerator).
+    #[serde(skip)]
+    pub jump_arc: Option<JumpArc>,
+}
+
+impl GeneratorConfig {
+    /// Rejects what the generators can't work with, and parses the tile colours,
+    /// so the generators don't have to.
+    fn validate(&mut self) -> Result<(), InvalidConfig> {
+        // the generators can be swapped at runtime, so the chunks have to fit every one of them
+        let min_size = [
+            OreLevelGenerator::MIN_CHUNK_SIZE,
+            WfcLevelGenerator::MIN_CHUNK_SIZE,
+        ]
+        .into_iter()
+        .fold((1, 1), |(w, h), (min_w, min_h)| {
+            (w.max(min_w), h.max(min_h))
+        });
+        if self.chunk_size.0 < min_size.0 || self.chunk_size.1 < min_size.1 {
+            return Err(InvalidConfig::ChunksTooSmall(self.chunk_size, min_size));
+        }
+        // the deprecated fields would be silently ignored
+        if self.noise.is_some() && (self.noise_scale.is_some() || self.octaves.is_some()) {
+            return Err(InvalidConfig::ConflictingNoise);
+        }
+        if !self.layers.is_empty() && !self.biomes.biomes.is_empty() {
+            return Err(InvalidConfig::ConflictingLayers);
+        }
+        let noises = [
+            self.noise.as_ref(),
+            Some(&self.biomes.map),
+            Some(&self.terrain.surface),
+            Some(&self.terrain.carve_mask),
+            Some(&self.terrain.island_mask),
+        ];
+        if self.noise_scale == Some(0.0) || noises.into_iter().flatten().any(Noise::zero_scale) {
+            return Err(InvalidConfig::ZeroScale);
+        }
+        if !(0.0..=1.0).contains(&self.caves.fill) {
+            return Err(InvalidConfig::Fill(self.caves.fill));
+        }
+
+        let biomes = &mut self.biomes.biomes;
+        let biome_layers = biomes.iter_mut().flat_map(|biome| biome.layers.iter_mut());
+        for layer in self.layers.iter_mut().chain(biome_layers) {
+            layer.parsed_color = Color::hex(&layer.color)?;
+        }
+        for hazards in biomes.iter_mut().filter_map(|biome| biome.hazards.as_mut()) {
+            hazards.parsed_color = Color::hex(&hazards.color)?;
+        }
+        Ok(())
+    }
+
+    /// The configured [`Noise`], seeded with `seed`.
+    pub fn noise(&self, seed: u32) -> Noise {
+        let noise = self.noise.clone().unwrap_or_else(|| {
+            Noise::perlin(0)
+                .fractal(Fractal {
+                    octaves: self.octaves.unwrap_or(1),
+                    ..default()
+                })
+                .scale(self.noise_scale.unwrap_or(NOISE_SCALE))
+        });
+        noise.seeded(seed)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct CaveConfig {
+    /// The chance of a tile starting out as rock, before smoothing.
+    pub fill: f64,
+    /// How often the rock is smoothed, every pass makes the caves rounder.
+    pub smoothing: u32,
+    /// How many tiles tunnels reach to either side of their center.
+    pub tunnel_radius: i32,
+    /// Pockets with fewer open tiles than this are filled with rock instead of connected.
+    pub min_pocket: usize,
+}
+
+impl Default for CaveConfig {
+    fn default() -> Self {
+        Self {
+            fill: 0.48,
+            smoothing: 4,
+            tunnel_radius: 1,
+            min_pocket: 8,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct TerrainConfig {
+    /// The height the surface varies around, in tiles.
+    pub height: i32,
+    /// How far the surface reaches above and below `height`, in tiles.
+    pub amplitude: f64,
+    /// The height of the surface along the x axis, sampled at `[x, 0]`.
+    pub surface: Noise,
+    /// The ground is carved out where this is at least `carve`, leaving overhangs and caves.
+    pub carve_mask: Noise,
+    pub carve: f64,
+    /// Islands float where this is above `islands`, within `island_heights`.
+    pub island_mask: Noise,
+    pub islands: f64,
+    /// The lowest and highest island tiles, in tiles above the surface.
+    pub island_heights: (i32, i32),
+    /// How many tiles of dirt there are between the grass and the stone.
+    pub dirt_depth: i32,
+}
+
+impl Default for TerrainConfig {
+    fn default() -> Self {
+        Self {
+            height: 0,
+            amplitude: 16.0,
+            surface: Noise::perlin(10).fractal(Fractal::default()).scale(64.0),
+            carve_mask: Noise::perlin(11)
+                .fractal(Fractal {
+                    octaves: 3,
+                    ..default()
+                })
+                .scale(12.0),
+            carve: 0.35,
+            island_mask: Noise::perlin(12)
+                .fractal(Fractal {
+                    octaves: 3,
+                    ..default()
+                })
+                .scale(10.0),
+            islands: 0.35,
+            island_heights: (8, 18),
+            dirt_depth: 3,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColliderStrategy {
+    /// One collider per tile. Neighbouring colliders leave seams the player can catch on.
+    PerTile,
+    /// Tiles are merged into as few rectangles as possible, each with its own collider.
+    #[default]
+    Rectangles,
+    /// The merged rectangles make up a single compound collider per chunk.
+    Compound,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ThresholdLayer {
+    pub threshold: f64,
+    /// Hex colour, used by [`ColorTile`](super::ColorTile)s.
+    pub color: String,
+    /// Index into the tile atlas, used by [`TexturedTile`](super::TexturedTile)s.
+    pub tex_index: usize,
+    pub z_index: i32,
+    /// Whether the tiles of this layer are one-way platforms, which characters
+    /// can jump up through and drop down through.
+    #[serde(default)]
+    pub one_way: bool,
+    /// The parsed colour.
+    #[serde(skip)]
+    pub parsed_color: Color,
+}
+
+impl ThresholdLayer {
+    pub fn collision(&self) -> TileCollision {
+        if self.one_way {
+            TileCollision::OneWay
+        } else {
+            TileCollision::Solid
+        }
+    }
+}
+
+/// Why a [`GeneratorConfig`] was rejected.
+#[derive(Debug, thiserror::Error)]
+pub enum InvalidConfig {
+    #[error("Could not parse tile color: {0}")]
+    Color(#[from] HexColorError),
+    #[error("The chunk size is {0:?}, but the generators need chunks of at least {1:?}")]
+    ChunksTooSmall((u32, u32), (u32, u32)),
+    #[error("`noise` replaces `noise_scale` and `octaves`, they can't be combined")]
+    ConflictingNoise,
+    #[error("`biomes` replace the top level `layers`, they can't be combined")]
+    ConflictingLayers,
+    #[error("Noise can't be scaled by 0")]
+    ZeroScale,
+    #[error("The cave fill is a chance between 0 and 1, but is {0}")]
+    Fill(f64),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rejection(config: &str) -> InvalidConfig {
+        match GeneratorConfigLoader::parse(config.as_bytes()) {
+            Err(GeneratorConfigLoaderError::Invalid(invalid)) => *invalid.downcast().unwrap(),
+            other => panic!("{config} wasn't rejected as invalid, but {other:?}"),
+        }
+    }
+
+    #[test]
+    fn invalid_configs_are_rejected() {
+        let config = |chunk_size: &str, color: &str| {
+            format!(
+                "(
+                    chunk_size: {chunk_size},
+                    layers: [(threshold: 0.2, color: \"{color}\", tex_index: 0, z_index: 0)],
+                )"
+            )
+        };
+        let valid = GeneratorConfigLoader::parse(config("(16, 16)", "#FF5733").as_bytes()).unwrap();
+        assert_eq!(valid.layers[0].parsed_color, Color::hex("#FF5733").unwrap());
+        assert!(matches!(
+            rejection(&config("(16, 16)", "#FF57G3")),
+            InvalidConfig::Color(_)
+        ));
+        for chunk_size in ["(0, 16)", "(16, 0)", "(3, 16)", "(16, 8)"] {
+            assert!(matches!(
+                rejection(&config(chunk_size, "#FF5733")),
+                InvalidConfig::ChunksTooSmall(..)
+            ));
+        }
+
+        assert!(matches!(
+            rejection(
+                "(chunk_size: (16, 16), noise: Some(Perlin()), octaves: Some(2), layers: [])"
+            ),
+            InvalidConfig::ConflictingNoise
+        ));
+        assert!(matches!(
+            rejection(
+                "(
+                    chunk_size: (16, 16),
+                    layers: [(threshold: 0.2, color: \"#FFFFFF\", tex_index: 0, z_index: 0)],
+                    biomes: (biomes: [(layers: [])]),
+                )"
+            ),
+            InvalidConfig::ConflictingLayers
+        ));
+        assert!(matches!(
+            rejection("(chunk_size: (16, 16), noise_scale: Some(0.0), layers: [])"),
+            InvalidConfig::ZeroScale
+        ));
+        assert!(matches!(
+            rejection(
+                "(chunk_size: (16, 16), layers: [], terrain: (surface: Average([Perlin(), Scale(Perlin(), 0.0)])))"
+            ),
+            InvalidConfig::ZeroScale
+        ));
+        assert!(matches!(
+            rejection("(chunk_size: (16, 16), layers: [], caves: (fill: 1.5))"),
+            InvalidConfig::Fill(_)
+        ));
+    }
+
+    #[test]
+    fn shipped_config_loads() {
+        let config = GeneratorConfigLoader::parse(include_bytes!(
+            "../../assets/level/default.generator.ron"
+        ))
+        .unwrap();
+        let hazards = config.biomes.biomes.iter().find_map(|b| b.hazards.as_ref());
+        assert_ne!(hazards.unwrap().parsed_color, Color::default());
+    }
+}
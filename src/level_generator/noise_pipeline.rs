@@ -0,0 +1,269 @@
+use noise::{NoiseFn, Perlin};
+use serde::Deserialize;
+
+/// Where the second component of a [`Noise::Warp`] offset is sampled, relative to the first,
+/// so both components are independent of each other.
+const WARP_OFFSET: [f64; 2] = [5.2, 1.3];
+
+/// A tree of noise functions, sampled at tile positions. Built in code or loaded as part of
+/// a [`GeneratorConfig`](super::GeneratorConfig), for example:
+///
+/// ```ron
+/// noise: Some(Scale(Warp(
+///     source: Fractal(Perlin(salt: 0), (kind: Ridged, octaves: 4)),
+///     by: Fractal(Perlin(salt: 1), (octaves: 2)),
+///     strength: 0.6,
+/// ), 12.5)),
+/// ```
+///
+/// Every leaf and combination stays within `-1.0..=1.0`, as long as its sources do.
+/// The noise has to be [`seeded`](Noise::seeded) before it is sampled.
+#[derive(Deserialize, Debug, Clone)]
+pub enum Noise {
+    /// Perlin noise with features about one unit apart. Leaves with different salts are independent.
+    Perlin {
+        #[serde(default)]
+        salt: u32,
+        #[serde(skip)]
+        perlin: Box<Perlin>,
+    },
+    Constant(f64),
+    /// Positions are divided by the scale before sampling the source, bigger values make bigger features.
+    Scale(Box<Noise>, f64),
+    /// Sums up octaves of the source, see [`Fractal`].
+    Fractal(Box<Noise>, Fractal),
+    /// Offsets the positions the source is sampled at by `by`, times `strength`,
+    /// which turns round blobs into swirly, stretched shapes.
+    Warp {
+        source: Box<Noise>,
+        by: Box<Noise>,
+        strength: f64,
+    },
+    /// The average of all sources.
+    Average(Vec<Noise>),
+    /// The product of all sources.
+    Multiply(Vec<Noise>),
+    /// `source` where `mask` is above `threshold`, `other` where it is below,
+    /// blending between them where `mask` is within `falloff` of the threshold.
+    Mask {
+        source: Box<Noise>,
+        other: Box<Noise>,
+        mask: Box<Noise>,
+        #[serde(default)]
+        threshold: f64,
+        #[serde(default)]
+        falloff: f64,
+    },
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct Fractal {
+    pub kind: FractalKind,
+    /// How many octaves are summed up, each adding finer detail.
+    pub octaves: u32,
+    /// How much the frequency grows from one octave to the next.
+    pub lacunarity: f64,
+    /// How much the amplitude shrinks from one octave to the next.
+    pub persistence: f64,
+}
+
+impl Default for Fractal {
+    fn default() -> Self {
+        Self {
+            kind: FractalKind::default(),
+            octaves: 4,
+            lacunarity: 2.0,
+            persistence: 0.5,
+        }
+    }
+}
+
+/// How every octave of a [`Fractal`] is shaped before it is summed up.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FractalKind {
+    /// Octaves are summed up as they are (fractal Brownian motion).
+    #[default]
+    Fbm,
+    /// Octaves are folded into sharp ridges where the source crosses zero, like mountain crests.
+    Ridged,
+    /// Octaves are folded into round bumps, like clouds or rolling hills.
+    Billow,
+}
+
+impl Noise {
+    pub fn perlin(salt: u32) -> Self {
+        Self::Perlin {
+            salt,
+            perlin: Box::default(),
+        }
+    }
+
+    pub fn scale(self, scale: f64) -> Self {
+        Self::Scale(Box::new(self), scale)
+    }
+
+    pub fn fractal(self, fractal: Fractal) -> Self {
+        Self::Fractal(Box::new(self), fractal)
+    }
+
+    /// Whether any [`Noise::Scale`] in the pipeline divides by zero.
+    pub fn zero_scale(&self) -> bool {
+        match self {
+            Self::Perlin { .. } | Self::Constant(_) => false,
+            Self::Scale(source, scale) => *scale == 0.0 || source.zero_scale(),
+            Self::Fractal(source, _) => source.zero_scale(),
+            Self::Warp { source, by, .. } => source.zero_scale() || by.zero_scale(),
+            Self::Average(sources) | Self::Multiply(sources) => {
+                sources.iter().any(Self::zero_scale)
+            }
+            Self::Mask {
+                source,
+                other,
+                mask,
+                ..
+            } => source.zero_scale() || other.zero_scale() || mask.zero_scale(),
+        }
+    }
+
+    /// Seeds every [`Noise::Perlin`] leaf with `seed` combined with its salt.
+    pub fn seeded(mut self, seed: u32) -> Self {
+        self.seed(seed);
+        self
+    }
+
+    fn seed(&mut self, seed: u32) {
+        match self {
+            Self::Perlin { salt, perlin } => {
+                **perlin = Perlin::new(seed.wrapping_add(salt.wrapping_mul(0x9E37_79B9)));
+            }
+            Self::Constant(_) => {}
+            Self::Scale(source, _) | Self::Fractal(source, _) => source.seed(seed),
+            Self::Warp { source, by, .. } => {
+                source.seed(seed);
+                by.seed(seed);
+            }
+            Self::Average(sources) | Self::Multiply(sources) => {
+                sources.iter_mut().for_each(|source| source.seed(seed));
+            }
+            Self::Mask {
+                source,
+                other,
+                mask,
+                ..
+            } => {
+                source.seed(seed);
+                other.seed(seed);
+                mask.seed(seed);
+            }
+        }
+    }
+
+    pub fn sample(&self, [x, y]: [f64; 2]) -> f64 {
+        match self {
+            Self::Perlin { perlin, .. } => perlin.get([x, y]),
+            Self::Constant(value) => *value,
+            Self::Scale(source, scale) => source.sample([x / scale, y / scale]),
+            Self::Fractal(source, fractal) => {
+                let (mut value, mut amplitude, mut frequency, mut total) = (0.0, 1.0, 1.0, 0.0);
+                for _ in 0..fractal.octaves.max(1) {
+                    let octave = source.sample([x * frequency, y * frequency]);
+                    let octave = match fractal.kind {
+                        FractalKind::Fbm => octave,
+                        FractalKind::Ridged => 1.0 - 2.0 * octave.abs(),
+                        FractalKind::Billow => 2.0 * octave.abs() - 1.0,
+                    };
+                    value += amplitude * octave;
+                    total += amplitude;
+                    amplitude *= fractal.persistence;
+                    frequency *= fractal.lacunarity;
+                }
+                value / total
+            }
+            Self::Warp {
+                source,
+                by,
+                strength,
+            } => {
+                let dx = by.sample([x, y]);
+                let dy = by.sample([x + WARP_OFFSET[0], y + WARP_OFFSET[1]]);
+                source.sample([x + dx * strength, y + dy * strength])
+            }
+            Self::Average(sources) => {
+                sources
+                    .iter()
+                    .map(|source| source.sample([x, y]))
+                    .sum::<f64>()
+                    / sources.len().max(1) as f64
+            }
+            Self::Multiply(sources) => sources.iter().map(|source| source.sample([x, y])).product(),
+            Self::Mask {
+                source,
+                other,
+                mask,
+                threshold,
+                falloff,
+            } => {
+                let mask = mask.sample([x, y]) - threshold;
+                let t = if *falloff > 0.0 {
+                    (mask / falloff * 0.5 + 0.5).clamp(0.0, 1.0)
+                } else if mask > 0.0 {
+                    1.0
+                } else {
+                    0.0
+                };
+                // skip sampling what is masked out entirely
+                match t {
+                    t if t <= 0.0 => other.sample([x, y]),
+                    t if t >= 1.0 => source.sample([x, y]),
+                    t => other.sample([x, y]) * (1.0 - t) + source.sample([x, y]) * t,
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pipeline() -> Noise {
+        ron::from_str(
+            "Mask(
+                source: Scale(Warp(
+                    source: Fractal(Perlin(salt: 0), (kind: Ridged, octaves: 4)),
+                    by: Fractal(Perlin(salt: 1), (octaves: 2)),
+                    strength: 0.6,
+                ), 12.5),
+                other: Fractal(Scale(Perlin(salt: 2), 8.0), (kind: Billow, lacunarity: 1.8, persistence: 0.6)),
+                mask: Average([Scale(Perlin(salt: 3), 40.0), Constant(0.2)]),
+                falloff: 0.1,
+            )",
+        )
+        .unwrap()
+    }
+
+    fn positions() -> impl Iterator<Item = [f64; 2]> {
+        (-50..50).flat_map(|x| (-50..50).map(move |y| [x as f64 * 1.7, y as f64 * 2.3]))
+    }
+
+    #[test]
+    fn pipelines_stay_within_range() {
+        let noise = pipeline().seeded(7);
+        for pos in positions() {
+            let value = noise.sample(pos);
+            assert!((-1.0..=1.0).contains(&value), "{value} at {pos:?}");
+        }
+    }
+
+    #[test]
+    fn seeds_and_salts_change_the_noise() {
+        let sample = |noise: &Noise| positions().map(|pos| noise.sample(pos)).collect::<Vec<_>>();
+        let noise = sample(&pipeline().seeded(7));
+        assert_eq!(sample(&pipeline().seeded(7)), noise);
+        assert_ne!(sample(&pipeline().seeded(8)), noise);
+
+        let salted = |salt| sample(&Noise::perlin(salt).scale(10.0).seeded(7));
+        assert_ne!(salted(0), salted(1));
+    }
+}
@@ -0,0 +1,393 @@
+use bevy::utils::HashSet;
+
+use crate::character_controller::JumpArc;
+
+use super::*;
+
+/// Only this fraction of what the [`JumpArc`] allows is used, so jumps don't have to be pixel perfect.
+const REACH_SAFETY: f32 = 0.8;
+/// The furthest the chain drops down between two pieces, in tiles.
+const MAX_DROP: i32 = 4;
+/// Gaps are never wider than this, even if the player could make them, in tiles.
+const MAX_GAP: i32 = 5;
+/// Free space kept above every tile the player stands on, in tiles.
+const HEADROOM: i32 = 3;
+/// How often a new piece is tried before falling back to extending the current one.
+const PIECE_ATTEMPTS: usize = 12;
+/// How often a decoration is tried per chunk, most of them won't fit.
+const DECORATION_ATTEMPTS: usize = 10;
+
+// the lowest and highest anchor in a chunk, relative to its bottom
+const ANCHOR_MIN: i32 = 3;
+const ANCHOR_MAX: i32 = 8;
+/// The longest the first piece of a chunk gets, in tiles.
+const FIRST_PIECE_MAX: i32 = 4;
+
+const ANCHOR_SALT: u64 = 0;
+const CHAIN_SALT: u64 = 1;
+
+const PATH_COLOR: &str = "#3E8948";
+const DECORATION_COLOR: &str = "#5A6988";
+
+/// Generates chains of platforms with occupancy-regulated extension
+/// ([Mawhorter & Mateas](https://www.cs.hmc.edu/~pmawhorter/research/papers/procedural_level_generation_using_occupancy_regulated_extension-Mawhorter_Mateas-2010.pdf)).
+///
+/// Every row of chunks is crossed by a chain of [`Piece`]s, each one placed within the player's
+/// [`JumpArc`] of the previous one, so the chain is playable by construction.
+/// Every piece occupies the space the player needs to stand on and jump off it, and no later
+/// piece or decoration may be placed there.
+///
+/// The chain enters every chunk at an anchor on its left border, which only depends on the seed
+/// and the chunk position, so each chunk can be generated on its own and still links up with
+/// its neighbours.
+pub struct OreLevelGenerator {
+    seed: u32,
+    chunk_size: (u32, u32),
+    reach: Reach,
+    path_color: Color,
+    decoration_color: Color,
+}
+
+impl OreLevelGenerator {
+    /// The smallest chunks the chain fits into: the highest anchor needs its headroom,
+    /// and after the first piece there has to be room left to walk over to any other anchor.
+    pub const MIN_CHUNK_SIZE: (u32, u32) = (
+        (FIRST_PIECE_MAX + ANCHOR_MAX - ANCHOR_MIN + 1) as u32,
+        (ANCHOR_MAX + HEADROOM + 1) as u32,
+    );
+
+    fn anchor(&self, ch_pos: (i32, i32)) -> i32 {
+        ch_pos.1 * self.chunk_size.1 as i32
+            + seeded_rng(self.seed, ANCHOR_SALT, ch_pos).gen_range(ANCHOR_MIN..=ANCHOR_MAX)
+    }
+}
+
+impl LevelGenerator for OreLevelGenerator {
+    type Tile = ColorTile;
+    fn new(seed: u32, config: &GeneratorConfig) -> Self {
+        let (width, height) = config.chunk_size;
+        let (min_width, min_height) = Self::MIN_CHUNK_SIZE;
+        assert!(
+            width >= min_width && height >= min_height,
+            "chunks of {width}x{height} tiles are too small for the ore generator"
+        );
+        let jump_arc = config
+            .jump_arc
+            .expect("the ore generator needs the player's jump arc");
+        Self {
+            seed,
+            chunk_size: config.chunk_size,
+            reach: Reach::new(&jump_arc),
+            path_color: Color::hex(PATH_COLOR).expect("could not parse tile color"),
+            decoration_color: Color::hex(DECORATION_COLOR).expect("could not parse tile color"),
+        }
+    }
+
+    fn chunk_size(&self) -> (u32, u32) {
+        self.chunk_size
+    }
+
+    fn generate_chunk(&self, (start_x, start_y): (i32, i32)) -> Chunk<Self::Tile> {
+        let (width, height) = self.chunk_size;
+        let ch_pos = (start_x / width as i32, start_y / height as i32);
+        let (end_x, end_y) = (start_x + width as i32, start_y + height as i32);
+        let reach = &self.reach;
+        let mut rng = seeded_rng(self.seed, CHAIN_SALT, ch_pos);
+
+        let target = (end_x, self.anchor((ch_pos.0 + 1, ch_pos.1)));
+        // pieces stay low enough for their headroom to fit into the chunk
+        let (min_y, max_y) = (start_y + 1, end_y - 1 - HEADROOM);
+
+        let mut occupancy = Occupancy::default();
+        let mut path = Vec::new();
+        let entry = (start_x, self.anchor(ch_pos));
+        let first = Piece::Flat {
+            len: rng.gen_range(2..=FIRST_PIECE_MAX),
+        };
+        occupancy.place(&first, entry, None);
+        path.extend(first.tiles(entry));
+        let mut exit = first.exit(entry);
+
+        // extend the chain while there is room left to still reach the next anchor afterwards
+        while end_x - exit.0 - 1 > 2 * reach.steps_between(exit.1, target.1) + 4 {
+            let candidate = (0..PIECE_ATTEMPTS).find_map(|_| {
+                let piece = Piece::random(&mut rng);
+                let rise = rng.gen_range(-MAX_DROP..=reach.max_rise);
+                let entry_y = exit.1 + rise;
+                let gap = rng.gen_range(1..=reach.max_gap(rise).max(1));
+                let entry = (exit.0 + gap + 1, entry_y);
+
+                let tiles = piece.tiles(entry);
+                let (exit_x, exit_y) = piece.exit(entry);
+                let steps = reach.steps_between(exit_y, target.1);
+                let fits = tiles.iter().all(|(x, y)| *x < end_x - 1 && (min_y..=max_y).contains(y))
+                    // never wander off too far to be able to reach the next anchor
+                    && steps <= 2
+                    && end_x - 1 - exit_x >= steps
+                    && reach.can_hop(exit, entry)
+                    && occupancy.fits(&piece, entry, Some(exit));
+                fits.then_some((piece, entry))
+            });
+
+            let (piece, entry) =
+                candidate.unwrap_or((Piece::Flat { len: 1 }, (exit.0 + 1, exit.1)));
+            occupancy.place(&piece, entry, Some(exit));
+            path.extend(piece.tiles(entry));
+            exit = piece.exit(entry);
+        }
+
+        // walk over to the next anchor one tile at a time, each step getting as close to its
+        // height as possible, or staying level if that's all there is room for.
+        // The chain left enough room for this, and nothing has been placed right of its exit yet.
+        while !reach.can_hop(exit, target) {
+            let rise = (target.1 - exit.1).clamp(-MAX_DROP, reach.max_rise);
+            let piece = Piece::Flat { len: 1 };
+            let Some(entry) = [(exit.0 + 1, exit.1 + rise), (exit.0 + 1, exit.1)]
+                .into_iter()
+                .find(|entry| entry.0 < end_x && occupancy.fits(&piece, *entry, Some(exit)))
+            else {
+                break;
+            };
+            occupancy.place(&piece, entry, Some(exit));
+            path.extend(piece.tiles(entry));
+            exit = piece.exit(entry);
+        }
+        debug_assert!(
+            reach.can_hop(exit, target),
+            "the chain of chunk {ch_pos:?} doesn't reach the next anchor"
+        );
+        occupancy.reserve_hop(exit, target);
+
+        // decorations are never part of the path, they only go where they don't get in the way,
+        // and as one-way platforms they never block the path from below
+        let mut decorations = Vec::new();
+        for _ in 0..DECORATION_ATTEMPTS {
+            let piece = Piece::Flat {
+                len: rng.gen_range(1..=3),
+            };
+            let entry = (
+                rng.gen_range(start_x..end_x - 3),
+                rng.gen_range(start_y..end_y),
+            );
+            if occupancy.fits(&piece, entry, None) {
+                occupancy.place(&piece, entry, None);
+                decorations.extend(piece.tiles(entry));
+            }
+        }
+
+        let data = path
+            .into_iter()
+            .map(|pos| ColorTile::new(pos, self.path_color, 0))
+            .chain(decorations.into_iter().map(|pos| {
+                ColorTile::new(pos, self.decoration_color, 0).with_collision(TileCollision::OneWay)
+            }))
+            .filter(|tile| {
+                let (x, y) = tile.pos();
+                (start_x..end_x).contains(&x) && (start_y..end_y).contains(&y)
+            })
+            .collect();
+
+        Chunk {
+            ch_pos,
+            width,
+            height,
+            data,
+        }
+    }
+}
+
+/// The part of a [`JumpArc`] the chain may use, in tiles.
+struct Reach {
+    /// How far up the player can jump.
+    max_rise: i32,
+    /// The widest gap the player can jump over, indexed by `rise + MAX_DROP`.
+    max_gaps: Vec<i32>,
+}
+
+impl Reach {
+    fn new(arc: &JumpArc) -> Self {
+        let max_rise = ((arc.max_height() * REACH_SAFETY / TILE_HEIGHT) as i32).max(1);
+        let max_gaps = (-MAX_DROP..=max_rise)
+            .map(|rise| {
+                let distance = arc
+                    .distance_at(rise as f32 * TILE_HEIGHT)
+                    .unwrap_or_default();
+                ((distance * REACH_SAFETY / TILE_WIDTH) as i32).min(MAX_GAP)
+            })
+            .collect();
+        Self { max_rise, max_gaps }
+    }
+
+    fn max_gap(&self, rise: i32) -> i32 {
+        self.max_gaps[(rise + MAX_DROP) as usize]
+    }
+
+    /// Whether the player can get from standing on `from` to standing on `to`.
+    fn can_hop(&self, from: (i32, i32), to: (i32, i32)) -> bool {
+        let rise = to.1 - from.1;
+        let gap = to.0 - from.0 - 1;
+        (-MAX_DROP..=self.max_rise).contains(&rise) && gap >= 0 && gap <= self.max_gap(rise)
+    }
+
+    /// How many hops it takes at least to get from height `from` to height `to`.
+    fn steps_between(&self, from: i32, to: i32) -> i32 {
+        let rise = to - from;
+        if rise > 0 {
+            (rise + self.max_rise - 1) / self.max_rise
+        } else {
+            (-rise + MAX_DROP - 1) / MAX_DROP
+        }
+    }
+}
+
+/// A piece of level the chain is extended with.
+/// Pieces are placed by their entry, the first tile the player lands on.
+#[derive(Clone, Copy, Debug)]
+enum Piece {
+    /// A horizontal platform.
+    Flat { len: i32 },
+    /// Single tiles going up or down one tile each.
+    Stairs { steps: i32, dir: i32 },
+    /// A column with its top as the entry.
+    Pillar { height: i32 },
+}
+
+impl Piece {
+    fn random(rng: &mut impl Rng) -> Self {
+        match rng.gen_range(0..4) {
+            0 => Self::Stairs {
+                steps: rng.gen_range(2..=4),
+                dir: if rng.gen() { 1 } else { -1 },
+            },
+            1 => Self::Pillar {
+                height: rng.gen_range(2..=5),
+            },
+            _ => Self::Flat {
+                len: rng.gen_range(2..=5),
+            },
+        }
+    }
+
+    fn tiles(&self, (x, y): (i32, i32)) -> Vec<(i32, i32)> {
+        match *self {
+            Self::Flat { len } => (0..len).map(|i| (x + i, y)).collect(),
+            Self::Stairs { steps, dir } => (0..steps).map(|i| (x + i, y + i * dir)).collect(),
+            Self::Pillar { height } => (0..height).map(|i| (x, y - i)).collect(),
+        }
+    }
+
+    /// The tiles the player stands on.
+    fn surface(&self, entry: (i32, i32)) -> Vec<(i32, i32)> {
+        match self {
+            Self::Pillar { .. } => vec![entry],
+            _ => self.tiles(entry),
+        }
+    }
+
+    /// The tile the player jumps off from to get to the next piece.
+    fn exit(&self, entry: (i32, i32)) -> (i32, i32) {
+        *self.surface(entry).last().unwrap_or(&entry)
+    }
+}
+
+/// Keeps track of solid tiles and of the space reserved for the player to move through.
+#[derive(Default)]
+struct Occupancy {
+    solid: HashSet<(i32, i32)>,
+    reserved: HashSet<(i32, i32)>,
+}
+
+impl Occupancy {
+    /// The space `piece` at `entry` needs to be walkable, including the jump onto it from `from`.
+    fn claims(piece: &Piece, entry: (i32, i32), from: Option<(i32, i32)>) -> Vec<(i32, i32)> {
+        let headroom = piece
+            .surface(entry)
+            .into_iter()
+            .flat_map(|(x, y)| (1..=HEADROOM).map(move |h| (x, y + h)));
+        headroom
+            .chain(from.map(|from| Self::hop(from, entry)).unwrap_or_default())
+            .collect()
+    }
+
+    /// A box around the arc of a jump from `from` to `to`.
+    fn hop(from: (i32, i32), to: (i32, i32)) -> Vec<(i32, i32)> {
+        let (low, high) = (from.1.min(to.1), from.1.max(to.1));
+        (from.0 + 1..to.0)
+            .flat_map(|x| (low + 1..=high + HEADROOM).map(move |y| (x, y)))
+            .collect()
+    }
+
+    fn fits(&self, piece: &Piece, entry: (i32, i32), from: Option<(i32, i32)>) -> bool {
+        piece
+            .tiles(entry)
+            .iter()
+            .all(|tile| !self.solid.contains(tile) && !self.reserved.contains(tile))
+            && Self::claims(piece, entry, from)
+                .iter()
+                .all(|tile| !self.solid.contains(tile))
+    }
+
+    fn place(&mut self, piece: &Piece, entry: (i32, i32), from: Option<(i32, i32)>) {
+        self.solid.extend(piece.tiles(entry));
+        self.reserved.extend(Self::claims(piece, entry, from));
+    }
+
+    fn reserve_hop(&mut self, from: (i32, i32), to: (i32, i32)) {
+        self.reserved.extend(Self::hop(from, to));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generator(seed: u32, chunk_size: (u32, u32)) -> OreLevelGenerator {
+        let mut config =
+            GeneratorConfigLoader::parse(b"(chunk_size: (16, 16), layers: [])").unwrap();
+        config.chunk_size = chunk_size;
+        config.jump_arc = Some(crate::player_jump_arc());
+        OreLevelGenerator::new(seed, &config)
+    }
+
+    #[test]
+    fn chains_connect_the_anchors() {
+        for chunk_size in [(16, 16), OreLevelGenerator::MIN_CHUNK_SIZE] {
+            let (width, height) = (chunk_size.0 as i32, chunk_size.1 as i32);
+            for seed in 0..20 {
+                let generator = generator(seed, chunk_size);
+                for ch_pos in [(0, 0), (1, 0), (-3, 2), (5, -4)] {
+                    let start = (ch_pos.0 * width, ch_pos.1 * height);
+                    let tiles = generator
+                        .generate_chunk(start)
+                        .data
+                        .iter()
+                        .map(|tile| tile.pos())
+                        .collect::<HashSet<_>>();
+                    // the player needs two free tiles above what they stand on
+                    let standable = tiles
+                        .iter()
+                        .copied()
+                        .filter(|(x, y)| (1..=2).all(|h| !tiles.contains(&(*x, y + h))))
+                        .collect::<Vec<_>>();
+
+                    let entry = (start.0, generator.anchor(ch_pos));
+                    let target = (start.0 + width, generator.anchor((ch_pos.0 + 1, ch_pos.1)));
+                    assert!(standable.contains(&entry), "seed {seed}, chunk {ch_pos:?}");
+                    let mut reached = HashSet::from([entry]);
+                    let mut stack = vec![entry];
+                    while let Some(from) = stack.pop() {
+                        for to in &standable {
+                            if generator.reach.can_hop(from, *to) && reached.insert(*to) {
+                                stack.push(*to);
+                            }
+                        }
+                    }
+                    assert!(
+                        reached.iter().any(|from| generator.reach.can_hop(*from, target)),
+                        "seed {seed}, chunk {ch_pos:?} of size {chunk_size:?}: no way to {target:?}"
+                    );
+                }
+            }
+        }
+    }
+}
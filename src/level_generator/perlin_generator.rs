@@ -1,39 +1,77 @@
-use noise::{NoiseFn, Perlin};
-
+use super::biome::{Biomes, HazardLayer};
+use super::noise_pipeline::Noise;
 use super::*;
 
-const NOISE_SCALE: f64 = 12.5;
+pub struct SimplePerlinLevelGenerator {
+    noise: Noise,
+    biomes: Biomes,
+    config: GeneratorConfig,
+}
+
+pub struct TexturedPerlinLevelGenerator {
+    noise: Noise,
+    biomes: Biomes,
+    config: GeneratorConfig,
+}
 
-pub struct SimplePerlinLevelGenerator;
-pub struct TexturedPerlinLevelGenerator;
+/// The noise at a tile position, with the density of its biome added.
+fn sample(noise: &Noise, biomes: &Biomes, (x, y): (i32, i32)) -> f64 {
+    noise.sample([x as f64, y as f64]) + biomes.density((x, y))
+}
+
+/// The hazard at an empty tile position, if there is ground right below it to lie on.
+fn hazard<'a>(noise: &Noise, biomes: &'a Biomes, (x, y): (i32, i32)) -> Option<&'a HazardLayer> {
+    let below = (x, y - 1);
+    if !biomes.biome(below).filled(sample(noise, biomes, below)) {
+        return None;
+    }
+    biomes.hazard((x, y))
+}
 
 impl LevelGenerator for SimplePerlinLevelGenerator {
     type Tile = ColorTile;
-    fn generate_chunk(seed: u32, (start_x, start_y): (i32, i32)) -> Chunk<Self::Tile> {
+    fn new(seed: u32, config: &GeneratorConfig) -> Self {
+        Self {
+            noise: config.noise(seed),
+            biomes: Biomes::new(seed, config),
+            config: config.clone(),
+        }
+    }
+
+    fn chunk_size(&self) -> (u32, u32) {
+        self.config.chunk_size
+    }
+
+    fn generate_chunk(&self, (start_x, start_y): (i32, i32)) -> Chunk<Self::Tile> {
         let mut data = Vec::new();
-        let perlin = Perlin::new(seed);
-        let (end_x, end_y) = (start_x + CHUNK_WIDTH as i32, start_y + CHUNK_HEIGHT as i32);
+        let (width, height) = self.config.chunk_size;
+        let (end_x, end_y) = (start_x + width as i32, start_y + height as i32);
         for x in start_x - 1..end_x + 1 {
             for y in start_y - 1..end_y + 1 {
-                let value = perlin.get([x as f64 / NOISE_SCALE, y as f64 / NOISE_SCALE]);
-                if value > 0.2 {
-                    data.push(ColorTile::new((x, y), "#FF5733", 0));
-                }
-                if value > 0.4 {
-                    data.push(ColorTile::new((x, y), "#FFC300", 1));
+                let biome = self.biomes.biome((x, y));
+                let value = sample(&self.noise, &self.biomes, (x, y));
+                for layer in &biome.layers {
+                    if value > layer.threshold {
+                        data.push(
+                            ColorTile::new((x, y), layer.parsed_color, layer.z_index)
+                                .with_collision(layer.collision()),
+                        );
+                    }
                 }
-                if value > 0.6 {
-                    data.push(ColorTile::new((x, y), "#DAF7A6", 2));
-                }
-                if value > 0.8 {
-                    data.push(ColorTile::new((x, y), "#C70039", 3));
+                if !biome.filled(value) {
+                    if let Some(hazard) = hazard(&self.noise, &self.biomes, (x, y)) {
+                        data.push(
+                            ColorTile::new((x, y), hazard.parsed_color, 0)
+                                .with_collision(TileCollision::Hazard),
+                        );
+                    }
                 }
             }
         }
         Chunk {
-            ch_pos: (start_x / CHUNK_WIDTH as i32, start_y / CHUNK_HEIGHT as i32),
-            width: CHUNK_WIDTH as u32,
-            height: CHUNK_HEIGHT as u32,
+            ch_pos: (start_x / width as i32, start_y / height as i32),
+            width,
+            height,
             data,
         }
     }
@@ -41,31 +79,48 @@ impl LevelGenerator for SimplePerlinLevelGenerator {
 
 impl LevelGenerator for TexturedPerlinLevelGenerator {
     type Tile = TexturedTile;
-    fn generate_chunk(seed: u32, (start_x, start_y): (i32, i32)) -> Chunk<Self::Tile> {
+    fn new(seed: u32, config: &GeneratorConfig) -> Self {
+        Self {
+            noise: config.noise(seed),
+            biomes: Biomes::new(seed, config),
+            config: config.clone(),
+        }
+    }
+
+    fn chunk_size(&self) -> (u32, u32) {
+        self.config.chunk_size
+    }
+
+    fn generate_chunk(&self, (start_x, start_y): (i32, i32)) -> Chunk<Self::Tile> {
         let mut data = Vec::new();
-        let perlin = Perlin::new(seed);
-        let (end_x, end_y) = (start_x + CHUNK_WIDTH as i32, start_y + CHUNK_HEIGHT as i32);
+        let (width, height) = self.config.chunk_size;
+        let (end_x, end_y) = (start_x + width as i32, start_y + height as i32);
         for x in start_x - 1..end_x + 1 {
             for y in start_y - 1..end_y + 1 {
-                let value = perlin.get([x as f64 / NOISE_SCALE, y as f64 / NOISE_SCALE]);
-                if value > 0.2 {
-                    data.push(TexturedTile::atlas((x, y), "#FF5733", 0, 0));
-                }
-                if value > 0.4 {
-                    data.push(TexturedTile::atlas((x, y), "#FFC300", 0, 1));
-                }
-                if value > 0.6 {
-                    data.push(TexturedTile::atlas((x, y), "#DAF7A6", 0, 2));
+                let biome = self.biomes.biome((x, y));
+                let value = sample(&self.noise, &self.biomes, (x, y));
+                for layer in &biome.layers {
+                    if value > layer.threshold {
+                        data.push(
+                            TexturedTile::new((x, y), layer.tex_index, layer.z_index)
+                                .with_collision(layer.collision()),
+                        );
+                    }
                 }
-                if value > 0.8 {
-                    data.push(TexturedTile::atlas((x, y), "#C70039", 0, 3));
+                if !biome.filled(value) {
+                    if let Some(hazard) = hazard(&self.noise, &self.biomes, (x, y)) {
+                        data.push(
+                            TexturedTile::new((x, y), hazard.tex_index, 0)
+                                .with_collision(TileCollision::Hazard),
+                        );
+                    }
                 }
             }
         }
         Chunk {
-            ch_pos: (start_x / CHUNK_WIDTH as i32, start_y / CHUNK_HEIGHT as i32),
-            width: CHUNK_WIDTH as u32,
-            height: CHUNK_HEIGHT as u32,
+            ch_pos: (start_x / width as i32, start_y / height as i32),
+            width,
+            height,
             data,
         }
     }
@@ -0,0 +1,674 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Mutex;
+
+use bevy::asset::{AsyncReadExt, ReadAssetBytesError};
+use bevy::render::color::HexColorError;
+use bevy::utils::thiserror;
+use image::{ImageFormat, RgbaImage};
+use serde::Deserialize;
+
+use super::*;
+
+/// How many placements [`Structures`] remembers. Once there are more, the least recently used
+/// half is forgotten, the cells around the chunks being generated are all used far more recently.
+const PLACEMENT_CACHE: usize = 4096;
+
+/// An authored structure, like a ruin or a bridge, loaded from `*.prefab.ron` files and stamped into
+/// the generated chunks wherever its [`Placement`] allows. Its tiles replace whatever the generator
+/// put there, and its clear cells carve out the generated tiles.
+///
+/// ```ron
+/// (
+///     tiles: Rows([
+///         "#  #",
+///         "#..#",
+///         "####",
+///     ]),
+///     palette: { '#': (color: "#8B9BB4") },
+///     placement: (rule: OnGround, spacing: (48, 32), chance: 0.5),
+/// )
+/// ```
+#[derive(Asset, TypePath, Debug, Clone)]
+pub struct Prefab {
+    /// Separates the placements of different prefabs, taken from the asset path.
+    salt: u64,
+    size: (i32, i32),
+    /// Relative to the bottom left corner. `None` clears the generated tiles.
+    cells: Vec<((i32, i32), Option<PrefabTile>)>,
+    placement: Placement,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PrefabTile {
+    color: Color,
+    z_index: i32,
+    collision: TileCollision,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct Placement {
+    pub rule: PlacementRule,
+    /// Structures are placed on a grid of cells this big, in tiles, at most one per cell.
+    /// A structure always lies within its cell, so structures of one prefab never overlap.
+    pub spacing: (i32, i32),
+    /// The chance of a cell getting a structure, if the rule allows one anywhere in it.
+    pub chance: f64,
+}
+
+impl Default for Placement {
+    fn default() -> Self {
+        Self {
+            rule: PlacementRule::default(),
+            spacing: (48, 32),
+            chance: 0.5,
+        }
+    }
+}
+
+/// Where in its cell a structure may be placed, checked against the tiles the generator put there.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PlacementRule {
+    /// Anywhere, no matter what is there.
+    Anywhere,
+    /// Standing on the ground: there is a tile below every column of the structure,
+    /// and none in its bottom row. The highest such place in the cell is taken.
+    #[default]
+    OnGround,
+    /// Floating in the air: there are no tiles within `clearance` tiles of the structure.
+    InAir { clearance: i32 },
+}
+
+/// The contents of a `*.prefab.ron` file.
+#[derive(Deserialize)]
+struct PrefabDefinition {
+    tiles: PrefabTiles,
+    #[serde(default)]
+    palette: HashMap<char, PaletteTile>,
+    #[serde(default)]
+    placement: Placement,
+}
+
+#[derive(Deserialize)]
+enum PrefabTiles {
+    /// Rows of characters, the top row first. Spaces leave the generated tiles alone,
+    /// dots clear them, and every other character is a tile from the palette.
+    Rows(Vec<String>),
+    /// A PNG image, relative to the prefab file, one pixel per tile. Fully transparent pixels leave
+    /// the generated tiles alone, pixels of the `clear` colour clear them,
+    /// and every other pixel is a solid tile of its colour.
+    Image {
+        path: String,
+        #[serde(default)]
+        clear: Option<String>,
+    },
+}
+
+#[derive(Deserialize)]
+struct PaletteTile {
+    /// Hex colour.
+    color: String,
+    #[serde(default)]
+    z_index: i32,
+    #[serde(default)]
+    collision: TileCollision,
+}
+
+impl Prefab {
+    /// Builds the prefab from its definition, `image` is the decoded image of [`PrefabTiles::Image`].
+    fn new(
+        definition: PrefabDefinition,
+        salt: u64,
+        image: Option<RgbaImage>,
+    ) -> Result<Self, PrefabLoaderError> {
+        let (width, height) = definition.placement.spacing;
+        if width <= 0 || height <= 0 {
+            return Err(PrefabLoaderError::Spacing(definition.placement.spacing));
+        }
+        let mut cells = Vec::new();
+        let size = match (definition.tiles, image) {
+            (PrefabTiles::Rows(rows), _) => {
+                let height = rows.len() as i32;
+                for (row, line) in rows.iter().enumerate() {
+                    // the first row is the top one
+                    let y = height - 1 - row as i32;
+                    for (x, c) in line.chars().enumerate() {
+                        let tile = match c {
+                            ' ' => continue,
+                            '.' => None,
+                            c => {
+                                let tile = definition
+                                    .palette
+                                    .get(&c)
+                                    .ok_or(PrefabLoaderError::UnknownTile(c))?;
+                                Some(PrefabTile {
+                                    color: Color::hex(&tile.color)?,
+                                    z_index: tile.z_index,
+                                    collision: tile.collision,
+                                })
+                            }
+                        };
+                        cells.push(((x as i32, y), tile));
+                    }
+                }
+                let width = rows.iter().map(|line| line.chars().count()).max();
+                (width.unwrap_or(0) as i32, height)
+            }
+            (PrefabTiles::Image { clear, .. }, Some(image)) => {
+                let clear = clear.map(Color::hex).transpose()?;
+                let height = image.height() as i32;
+                for (x, row, pixel) in image.enumerate_pixels() {
+                    let [r, g, b, a] = pixel.0;
+                    if a == 0 {
+                        continue;
+                    }
+                    let color = Color::rgba_u8(r, g, b, a);
+                    let tile = (Some(color) != clear).then_some(PrefabTile {
+                        color,
+                        z_index: 0,
+                        collision: TileCollision::Solid,
+                    });
+                    // image rows go down, world rows go up
+                    cells.push(((x as i32, height - 1 - row as i32), tile));
+                }
+                (image.width() as i32, height)
+            }
+            (PrefabTiles::Image { path, .. }, None) => {
+                return Err(PrefabLoaderError::MissingImage(path));
+            }
+        };
+
+        if cells.is_empty() {
+            return Err(PrefabLoaderError::Empty);
+        }
+
+        Ok(Self {
+            salt,
+            size,
+            cells,
+            placement: definition.placement,
+        })
+    }
+
+    /// The cell size of the placement grid, never smaller than the structure.
+    fn cell_size(&self) -> (i32, i32) {
+        let (width, height) = self.placement.spacing;
+        (width.max(self.size.0), height.max(self.size.1))
+    }
+
+    /// The bottom left corner of the structure in placement cell `cell`, if it gets one.
+    /// `occupied` tells whether the generator put a tile at a position.
+    fn place(
+        &self,
+        seed: u32,
+        cell: (i32, i32),
+        occupied: &mut impl FnMut((i32, i32)) -> bool,
+    ) -> Option<(i32, i32)> {
+        let mut rng = seeded_rng(seed, self.salt, cell);
+        if !rng.gen_bool(self.placement.chance.clamp(0.0, 1.0)) {
+            return None;
+        }
+
+        let (width, height) = self.size;
+        let (cell_width, cell_height) = self.cell_size();
+        let (min_x, min_y) = (cell.0 * cell_width, cell.1 * cell_height);
+        let x = rng.gen_range(min_x..=min_x + cell_width - width);
+        let y = rng.gen_range(min_y..=min_y + cell_height - height);
+        let columns = x..x + width;
+
+        match self.placement.rule {
+            PlacementRule::Anywhere => Some((x, y)),
+            PlacementRule::OnGround => (min_y..=min_y + cell_height - height)
+                .rev()
+                .find(|y| {
+                    columns
+                        .clone()
+                        .all(|x| !occupied((x, *y)) && occupied((x, y - 1)))
+                })
+                .map(|y| (x, y)),
+            PlacementRule::InAir { clearance } => {
+                let free = (x - clearance..x + width + clearance)
+                    .all(|x| (y - clearance..y + height + clearance).all(|y| !occupied((x, y))));
+                free.then_some((x, y))
+            }
+        }
+    }
+}
+
+/// The [`Prefab`]s a generator stamps into its chunks.
+#[derive(Default)]
+pub struct Structures {
+    seed: u32,
+    prefabs: Vec<Prefab>,
+    /// Several chunks share a cell, checking the placement rules again for each would
+    /// generate the cell's chunks again.
+    placements: Mutex<Placements>,
+}
+
+impl Structures {
+    pub fn new(seed: u32, prefabs: Vec<Prefab>) -> Self {
+        Self {
+            seed,
+            prefabs,
+            placements: default(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.prefabs.is_empty()
+    }
+
+    /// Stamps the structures overlapping the chunk into its tiles. `occupied` tells whether the
+    /// generator put a tile at a position, the placement rules are checked against that,
+    /// so structures come out the same no matter which chunk places them.
+    ///
+    /// Generated tiles covered by a structure are removed from the whole chunk, but the
+    /// structure's own tiles are only added within the chunk, so neighbours don't both add them.
+    pub fn stamp(
+        &self,
+        chunk: &mut Chunk<Box<dyn Tile>>,
+        start: (i32, i32),
+        mut occupied: impl FnMut((i32, i32)) -> bool,
+    ) {
+        let end = (start.0 + chunk.width as i32, start.1 + chunk.height as i32);
+        // generators may put tiles past the chunk's edges, the cells those lie in count as well,
+        // so their copies from the neighbouring chunks are removed
+        let (min, max) = chunk
+            .data
+            .iter()
+            .map(|tile| tile.pos())
+            .fold((start, (end.0 - 1, end.1 - 1)), |(min, max), (x, y)| {
+                ((min.0.min(x), min.1.min(y)), (max.0.max(x), max.1.max(y)))
+            });
+        // later prefabs win over earlier ones where they overlap
+        let mut stamps = BTreeMap::new();
+        for (index, prefab) in self.prefabs.iter().enumerate() {
+            let (cell_width, cell_height) = prefab.cell_size();
+            let cells_x = min.0.div_euclid(cell_width)..=max.0.div_euclid(cell_width);
+            let cells_y = min.1.div_euclid(cell_height)..=max.1.div_euclid(cell_height);
+            for cell in cells_x.flat_map(|x| cells_y.clone().map(move |y| (x, y))) {
+                let Some((x, y)) = self.place(index, cell, &mut occupied) else {
+                    continue;
+                };
+                for ((dx, dy), tile) in &prefab.cells {
+                    stamps.insert((x + dx, y + dy), *tile);
+                }
+            }
+        }
+
+        chunk.data.retain(|tile| !stamps.contains_key(&tile.pos()));
+        let inside =
+            |(x, y): (i32, i32)| (start.0..end.0).contains(&x) && (start.1..end.1).contains(&y);
+        for (pos, tile) in stamps {
+            if let Some(tile) = tile.filter(|_| inside(pos)) {
+                chunk.data.push(Box::new(
+                    ColorTile::new(pos, tile.color, tile.z_index).with_collision(tile.collision),
+                ));
+            }
+        }
+    }
+
+    /// [`Prefab::place`] for the prefab at `index`, computed once per cell.
+    fn place(
+        &self,
+        index: usize,
+        cell: (i32, i32),
+        occupied: &mut impl FnMut((i32, i32)) -> bool,
+    ) -> Option<(i32, i32)> {
+        let key = (index, cell);
+        if let Some(placement) = self.placements.lock().unwrap().get(key) {
+            return placement;
+        }
+        // not locked while placing, so chunks in other cells aren't held up
+        let placement = self.prefabs[index].place(self.seed, cell, occupied);
+        self.placements.lock().unwrap().insert(key, placement);
+        placement
+    }
+}
+
+/// Where the structures are in the cells placed recently, by prefab index and cell,
+/// at most [`PLACEMENT_CACHE`] of them.
+#[derive(Default)]
+struct Placements {
+    /// The placement, and the use it was last used at.
+    cells: HashMap<(usize, (i32, i32)), (Option<(i32, i32)>, u64)>,
+    /// How often placements were looked up or inserted.
+    uses: u64,
+}
+
+impl Placements {
+    fn get(&mut self, key: (usize, (i32, i32))) -> Option<Option<(i32, i32)>> {
+        self.uses += 1;
+        let uses = self.uses;
+        self.cells.get_mut(&key).map(|(placement, used)| {
+            *used = uses;
+            *placement
+        })
+    }
+
+    fn insert(&mut self, key: (usize, (i32, i32)), placement: Option<(i32, i32)>) {
+        self.uses += 1;
+        self.cells.insert(key, (placement, self.uses));
+        if self.cells.len() > PLACEMENT_CACHE {
+            let oldest = self.uses - (PLACEMENT_CACHE / 2) as u64;
+            self.cells.retain(|_, (_, used)| *used > oldest);
+        }
+    }
+}
+
+#[non_exhaustive]
+#[derive(Debug, thiserror::Error)]
+pub enum PrefabLoaderError {
+    #[error("Could not load asset: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not parse RON: {0}")]
+    RonSpannedError(#[from] ron::error::SpannedError),
+    #[error("Could not read prefab image: {0}")]
+    ReadImage(#[from] ReadAssetBytesError),
+    #[error("Could not decode prefab image: {0}")]
+    DecodeImage(#[from] image::ImageError),
+    #[error("Prefab image {0} was not loaded")]
+    MissingImage(String),
+    #[error("Could not parse tile color: {0}")]
+    Color(#[from] HexColorError),
+    #[error("The tile {0:?} is missing from the palette")]
+    UnknownTile(char),
+    #[error("The placement spacing has to be positive, but is {0:?}")]
+    Spacing((i32, i32)),
+    #[error("The prefab has no tiles")]
+    Empty,
+}
+
+#[derive(Default)]
+pub struct PrefabLoader;
+
+impl bevy::asset::AssetLoader for PrefabLoader {
+    type Asset = Prefab;
+    type Settings = ();
+    type Error = PrefabLoaderError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut bevy::asset::io::Reader,
+        _settings: &'a Self::Settings,
+        load_context: &'a mut bevy::asset::LoadContext,
+    ) -> bevy::utils::BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            let definition = ron::de::from_bytes::<PrefabDefinition>(&bytes)?;
+
+            let image = match &definition.tiles {
+                PrefabTiles::Image { path, .. } => {
+                    let path = load_context
+                        .path()
+                        .parent()
+                        .unwrap_or(Path::new(""))
+                        .join(path);
+                    let bytes = load_context.read_asset_bytes(path).await?;
+                    let image = image::load_from_memory_with_format(&bytes, ImageFormat::Png)?;
+                    Some(image.into_rgba8())
+                }
+                PrefabTiles::Rows(_) => None,
+            };
+
+            Prefab::new(definition, salt(load_context.path()), image)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["prefab.ron"]
+    }
+}
+
+/// The FNV-1a hash of `path`, which stays the same between runs, unlike the std hasher's.
+fn salt(path: &Path) -> u64 {
+    path.to_string_lossy()
+        .bytes()
+        .fold(0xCBF2_9CE4_8422_2325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100_0000_01B3)
+        })
+}
+
+pub struct PrefabPlugin;
+
+impl Plugin for PrefabPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<Prefab>()
+            .init_asset_loader::<PrefabLoader>();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    /// Ground everywhere below `y = 0`. Like the Perlin generators, chunks also get the tiles
+    /// `margin` tiles past their edges.
+    struct Flat {
+        chunk_size: (u32, u32),
+        margin: i32,
+        /// How many chunks were generated.
+        generated: AtomicUsize,
+    }
+
+    impl LevelGenerator for Flat {
+        type Tile = ColorTile;
+        fn new(_seed: u32, config: &GeneratorConfig) -> Self {
+            Self {
+                chunk_size: config.chunk_size,
+                margin: 0,
+                generated: default(),
+            }
+        }
+
+        fn chunk_size(&self) -> (u32, u32) {
+            self.chunk_size
+        }
+
+        fn generate_chunk(&self, (start_x, start_y): (i32, i32)) -> Chunk<Self::Tile> {
+            self.generated.fetch_add(1, Ordering::Relaxed);
+            let (width, height) = self.chunk_size;
+            let (end_x, end_y) = (start_x + width as i32, start_y + height as i32);
+            let margin = self.margin;
+            let data = (start_x - margin..end_x + margin)
+                .flat_map(|x| (start_y - margin..end_y + margin).map(move |y| (x, y)))
+                .filter(|(_, y)| *y < 0)
+                .map(|pos| ColorTile::new(pos, Color::WHITE, 0))
+                .collect();
+            Chunk {
+                ch_pos: (
+                    start_x.div_euclid(width as i32),
+                    start_y.div_euclid(height as i32),
+                ),
+                width,
+                height,
+                data,
+            }
+        }
+    }
+
+    fn generator(chunk_size: u32, margin: i32, prefab: &str) -> BoxedTiles<Flat> {
+        let prefab = Prefab::new(ron::from_str(prefab).unwrap(), 0, None).unwrap();
+        BoxedTiles {
+            generator: Flat {
+                chunk_size: (chunk_size, chunk_size),
+                margin,
+                generated: default(),
+            },
+            structures: Structures::new(3, vec![prefab]),
+        }
+    }
+
+    /// The tiles of the chunks covering `-32..32` on both axes, sorted.
+    fn tiles(generator: &BoxedTiles<Flat>) -> Vec<((i32, i32), TileCollision)> {
+        let size = generator.chunk_size().0 as i32;
+        let starts = (-32..32).step_by(size as usize);
+        let mut tiles = starts
+            .clone()
+            .flat_map(|x| starts.clone().map(move |y| (x, y)))
+            .flat_map(|start| generator.generate_chunk(start).data)
+            .map(|tile| (tile.pos(), tile.collision()))
+            .collect::<Vec<_>>();
+        tiles.sort_by_key(|(pos, _)| *pos);
+        tiles
+    }
+
+    #[test]
+    fn structures_span_chunk_borders() {
+        let prefab = r##"(
+            tiles: Rows([
+                "^  ^",
+                "^..^",
+                "^^^^",
+            ]),
+            palette: { '^': (color: "#E43B44", collision: Hazard) },
+            placement: (rule: OnGround, spacing: (10, 16), chance: 1.0),
+        )"##;
+        let small = tiles(&generator(8, 0, prefab));
+        assert_eq!(tiles(&generator(32, 0, prefab)), small);
+
+        let mut positions = small.iter().map(|(pos, _)| *pos).collect::<Vec<_>>();
+        positions.dedup();
+        assert_eq!(positions.len(), small.len());
+
+        let hazards = small
+            .iter()
+            .filter(|(_, collision)| *collision == TileCollision::Hazard)
+            .map(|(pos, _)| *pos)
+            .collect::<Vec<_>>();
+        assert!(!hazards.is_empty());
+        // every structure stands right on the ground
+        assert!(hazards.iter().all(|(_, y)| (0..3).contains(y)));
+    }
+
+    #[test]
+    fn structures_override_generated_tiles() {
+        let prefab = r##"(
+            tiles: Rows([
+                "..",
+                "^^",
+            ]),
+            palette: { '^': (color: "#E43B44", collision: Hazard) },
+            placement: (rule: Anywhere, spacing: (2, 2), chance: 1.0),
+        )"##;
+        // the cells' edges are the chunks' edges, so the tiles past them are in other cells
+        for margin in [0, 1] {
+            let tiles = tiles(&generator(16, margin, prefab));
+            // every other row is cleared, the rest is covered by the structure's tiles
+            assert_eq!(tiles.len(), 64 * 32, "margin {margin}");
+            assert!(
+                tiles.iter().all(|((_, y), collision)| y.rem_euclid(2) == 0
+                    && *collision == TileCollision::Hazard),
+                "margin {margin}"
+            );
+        }
+    }
+
+    #[test]
+    fn placements_are_shared_between_chunks() {
+        let prefab = r##"(
+            tiles: Rows(["^^^^"]),
+            palette: { '^': (color: "#E43B44", collision: Hazard) },
+            placement: (rule: OnGround, spacing: (32, 32), chance: 1.0),
+        )"##;
+        let generator = generator(8, 0, prefab);
+        tiles(&generator);
+        // the 64 chunks themselves, and once more those the cells' placement rules look at
+        let generated = generator.generator.generated.load(Ordering::Relaxed);
+        assert!(generated <= 2 * 64, "{generated} chunks generated");
+    }
+
+    #[test]
+    fn spawn_points_avoid_structures() {
+        // hazards on the ground with gaps between them
+        let prefab = r##"(
+            tiles: Rows(["^^^^^^^^"]),
+            palette: { '^': (color: "#E43B44", collision: Hazard) },
+            placement: (rule: OnGround, spacing: (16, 16), chance: 1.0),
+        )"##;
+        let generator = generator(16, 0, prefab);
+        let tiles = tiles(&generator);
+        let hazard = |pos| tiles.contains(&(pos, TileCollision::Hazard));
+        let covered = (-16..16).find(|x| hazard((*x, 0))).unwrap();
+        let area = SpawnArea {
+            width: 1,
+            height: 2,
+            max_drop: 3,
+        };
+        // the wrapped generator picks the spot right above the hazard
+        let near = (covered, 1);
+        assert_eq!(
+            generator.generator.find_spawn_point(near, &area),
+            Some(near)
+        );
+
+        // which is moved into a gap between the stamped hazards
+        let (x, y) = generator.find_spawn_point(near, &area).unwrap();
+        assert!((0..=area.max_drop).contains(&y));
+        assert!((x - 1..=x + 1).all(|x| (-1..=y + 2).all(|y| !hazard((x, y)))));
+    }
+
+    #[test]
+    fn invalid_prefabs_are_rejected() {
+        let prefab = |rows: &str, spacing: &str| {
+            let definition = format!(
+                r##"(
+                    tiles: Rows({rows}),
+                    palette: {{ '#': (color: "#FFFFFF") }},
+                    placement: (rule: Anywhere, spacing: {spacing}, chance: 1.0),
+                )"##
+            );
+            Prefab::new(ron::from_str(&definition).unwrap(), 0, None)
+        };
+        assert!(prefab(r##"["#"]"##, "(4, 4)").is_ok());
+        for spacing in ["(0, 4)", "(4, 0)", "(-4, 4)"] {
+            assert!(matches!(
+                prefab(r##"["#"]"##, spacing),
+                Err(PrefabLoaderError::Spacing(_))
+            ));
+        }
+        for rows in ["[]", r#"[""]"#, r#"["  "]"#] {
+            assert!(matches!(
+                prefab(rows, "(4, 4)"),
+                Err(PrefabLoaderError::Empty)
+            ));
+        }
+    }
+
+    #[test]
+    fn placements_forget_the_least_recently_used() {
+        let mut placements = Placements::default();
+        for x in 0..PLACEMENT_CACHE as i32 {
+            placements.insert((0, (x, 0)), None);
+            // the first cell keeps getting used
+            assert_eq!(placements.get((0, (0, 0))), Some(None));
+        }
+        placements.insert((0, (-1, 0)), Some((1, 2)));
+        assert!(placements.cells.len() <= PLACEMENT_CACHE);
+        assert_eq!(placements.get((0, (-1, 0))), Some(Some((1, 2))));
+        assert_eq!(placements.get((0, (0, 0))), Some(None));
+        assert_eq!(placements.get((0, (1, 0))), None);
+    }
+
+    #[test]
+    fn shipped_prefabs_load() {
+        let ruin = ron::from_str(include_str!("../../assets/level/prefabs/ruin.prefab.ron"));
+        let ruin = Prefab::new(ruin.unwrap(), 0, None).unwrap();
+        assert_eq!(ruin.size, (11, 7));
+
+        let shrine = ron::from_str(include_str!(
+            "../../assets/level/prefabs/sky_shrine.prefab.ron"
+        ));
+        let image = image::load_from_memory_with_format(
+            include_bytes!("../../assets/level/prefabs/sky_shrine.png"),
+            ImageFormat::Png,
+        );
+        let shrine = Prefab::new(shrine.unwrap(), 1, Some(image.unwrap().into_rgba8())).unwrap();
+        assert_eq!(shrine.size, (11, 8));
+        assert!(shrine.cells.iter().all(|(_, tile)| tile.is_some()));
+    }
+}
@@ -0,0 +1,189 @@
+use super::noise_pipeline::Noise;
+use super::*;
+
+const GRASS_COLOR: &str = "#63C74D";
+const DIRT_COLOR: &str = "#8F563B";
+const STONE_COLOR: &str = "#5A6988";
+
+/// The ground right below the surface is never carved, so there always is a surface to walk on.
+const CRUST: i32 = 2;
+/// How far [`TerrainLevelGenerator::find_spawn_point`] searches to either side, in tiles.
+const SPAWN_SEARCH: i32 = 64;
+
+/// Generates side-scroller terrain: a continuous ground surface, whose height is 1D noise
+/// along the x axis, with a 2D mask carving overhangs and caves into the ground and
+/// raising islands into the sky.
+///
+/// Every tile only depends on the seed and its position, so chunks are seamless without any overlap.
+/// The top tile of the ground is grass, followed by a few tiles of dirt, and stone below that.
+pub struct TerrainLevelGenerator {
+    chunk_size: (u32, u32),
+    terrain: TerrainConfig,
+    surface: Noise,
+    carve: Noise,
+    islands: Noise,
+    grass: Color,
+    dirt: Color,
+    stone: Color,
+}
+
+impl TerrainLevelGenerator {
+    /// The height of the ground surface in column `x`.
+    fn surface(&self, x: i32) -> i32 {
+        self.terrain.height + (self.surface.sample([x as f64, 0.0]) * self.terrain.amplitude) as i32
+    }
+
+    fn solid(&self, (x, y): (i32, i32)) -> bool {
+        let surface = self.surface(x);
+        if y <= surface {
+            y > surface - CRUST || self.carve.sample([x as f64, y as f64]) < self.terrain.carve
+        } else {
+            let (min, max) = self.terrain.island_heights;
+            (min..=max).contains(&(y - surface))
+                && self.islands.sample([x as f64, y as f64]) > self.terrain.islands
+        }
+    }
+
+    /// How many solid tiles there are right above a solid tile, up to `limit`.
+    fn depth(&self, (x, y): (i32, i32), limit: i32) -> i32 {
+        (1..=limit)
+            .find(|dy| !self.solid((x, y + dy)))
+            .map_or(limit, |dy| dy - 1)
+    }
+}
+
+impl LevelGenerator for TerrainLevelGenerator {
+    type Tile = ColorTile;
+    fn new(seed: u32, config: &GeneratorConfig) -> Self {
+        let color = |hex| Color::hex(hex).expect("could not parse tile color");
+        Self {
+            chunk_size: config.chunk_size,
+            terrain: config.terrain.clone(),
+            surface: config.terrain.surface.clone().seeded(seed),
+            carve: config.terrain.carve_mask.clone().seeded(seed),
+            islands: config.terrain.island_mask.clone().seeded(seed),
+            grass: color(GRASS_COLOR),
+            dirt: color(DIRT_COLOR),
+            stone: color(STONE_COLOR),
+        }
+    }
+
+    fn chunk_size(&self) -> (u32, u32) {
+        self.chunk_size
+    }
+
+    /// Stands on the surface, in the column closest to `near` where `area` is free.
+    fn find_spawn_point(&self, near: (i32, i32), area: &SpawnArea) -> Option<(i32, i32)> {
+        (0..=SPAWN_SEARCH)
+            .flat_map(|distance| [near.0 - distance, near.0 + distance])
+            .map(|x| (x, self.surface(x) + 1))
+            .find(|(x, y)| {
+                let columns = *x..x + area.width;
+                let empty = columns
+                    .clone()
+                    .all(|cx| (*y..y + area.height).all(|cy| !self.solid((cx, cy))));
+                let grounded = (1..=area.max_drop)
+                    .any(|drop| columns.clone().any(|cx| self.solid((cx, y - drop))));
+                empty && grounded
+            })
+    }
+
+    fn generate_chunk(&self, (start_x, start_y): (i32, i32)) -> Chunk<Self::Tile> {
+        let (width, height) = self.chunk_size;
+        let ch_pos = (
+            start_x.div_euclid(width as i32),
+            start_y.div_euclid(height as i32),
+        );
+        let mut data = Vec::new();
+        for x in start_x..start_x + width as i32 {
+            for y in start_y..start_y + height as i32 {
+                if !self.solid((x, y)) {
+                    continue;
+                }
+                let color = match self.depth((x, y), self.terrain.dirt_depth + 1) {
+                    0 => self.grass,
+                    depth if depth <= self.terrain.dirt_depth => self.dirt,
+                    _ => self.stone,
+                };
+                data.push(ColorTile::new((x, y), color, 0));
+            }
+        }
+        Chunk {
+            ch_pos,
+            width,
+            height,
+            data,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generator(chunk_size: (u32, u32)) -> TerrainLevelGenerator {
+        let mut config: GeneratorConfig =
+            ron::from_str("(chunk_size: (16, 16), layers: [])").unwrap();
+        config.chunk_size = chunk_size;
+        TerrainLevelGenerator::new(11, &config)
+    }
+
+    fn tiles(generator: &TerrainLevelGenerator, starts: &[(i32, i32)]) -> Vec<((i32, i32), Color)> {
+        let mut tiles = starts
+            .iter()
+            .flat_map(|start| generator.generate_chunk(*start).data)
+            .map(|tile| (tile.pos(), tile.color))
+            .collect::<Vec<_>>();
+        tiles.sort_by_key(|(pos, _)| *pos);
+        tiles
+    }
+
+    #[test]
+    fn chunks_are_seamless() {
+        // the same area, once as one big chunk and once as four small ones
+        let big = tiles(&generator((32, 32)), &[(-16, -16)]);
+        let small = tiles(
+            &generator((16, 16)),
+            &[(-16, -16), (0, -16), (-16, 0), (0, 0)],
+        );
+        assert_eq!(big, small);
+    }
+
+    #[test]
+    fn ground_is_layered_by_depth() {
+        let generator = generator((16, 16));
+        for x in -100..100 {
+            let surface = generator.surface(x);
+            let dirt_depth = generator.terrain.dirt_depth;
+            let color = |y: i32| {
+                let tiles = tiles(
+                    &generator,
+                    &[(x.div_euclid(16) * 16, y.div_euclid(16) * 16)],
+                );
+                tiles
+                    .into_iter()
+                    .find(|(pos, _)| *pos == (x, y))
+                    .map(|(_, color)| color)
+            };
+            assert_eq!(color(surface), Some(generator.grass), "column {x}");
+            assert_eq!(color(surface - 1), Some(generator.dirt), "column {x}");
+            let below = surface - dirt_depth - 1;
+            if (below..surface).all(|y| generator.solid((x, y))) {
+                assert_eq!(color(below), Some(generator.stone), "column {x}");
+            }
+        }
+    }
+
+    #[test]
+    fn spawn_points_are_on_the_ground() {
+        let generator = generator((16, 16));
+        let area = SpawnArea {
+            width: 1,
+            height: 2,
+            max_drop: 3,
+        };
+        let (x, y) = generator.find_spawn_point((5, 40), &area).unwrap();
+        assert!(generator.solid((x, y - 1)));
+        assert!(!generator.solid((x, y)) && !generator.solid((x, y + 1)));
+    }
+}
@@ -1,100 +1,154 @@
 use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::loading::TileAssets;
 
 pub const TILE_HEIGHT: f32 = 32.0;
 pub const TILE_WIDTH: f32 = 32.0;
 pub const TILE_SCALE: f32 = 32.0;
 
+/// How characters collide with a tile.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TileCollision {
+    #[default]
+    Solid,
+    /// Only solid from above, characters jump up through it and drop down through it.
+    OneWay,
+    /// Solid, and sends characters touching it back to the spawn point, see [`Hazard`](super::Hazard).
+    Hazard,
+}
+
 pub trait Tile
 where
-    Self: Sized + Send + Sync + 'static,
+    Self: Send + Sync + 'static,
 {
-    fn make_sprite_bundle(&self) -> SpriteBundle;
+    /// Spawns the sprite of this tile. Tiles backed by a texture atlas
+    /// take their handles from `tile_assets`.
+    fn spawn(&self, child_builder: &mut ChildBuilder, tile_assets: &TileAssets);
     fn pos(&self) -> (i32, i32);
+    fn collision(&self) -> TileCollision {
+        TileCollision::Solid
+    }
 }
 
-#[derive(Eq, PartialEq, Debug)]
+impl Tile for Box<dyn Tile> {
+    fn spawn(&self, child_builder: &mut ChildBuilder, tile_assets: &TileAssets) {
+        (**self).spawn(child_builder, tile_assets);
+    }
+
+    fn pos(&self) -> (i32, i32) {
+        (**self).pos()
+    }
+
+    fn collision(&self) -> TileCollision {
+        (**self).collision()
+    }
+}
+
+#[derive(PartialEq, Debug)]
 pub struct ColorTile {
     pos: (i32, i32),
-    pub(crate) color: &'static str,
+    pub(crate) color: Color,
     pub(crate) z_index: i32,
+    pub(crate) collision: TileCollision,
 }
 
 impl Tile for ColorTile {
-    fn make_sprite_bundle(&self) -> SpriteBundle {
-        SpriteBundle {
-            sprite: Sprite {
-                color: Color::hex(self.color).expect("could not parse tile color"),
-                ..default()
-            },
-            transform: Transform::from_xyz(
-                self.pos.0 as f32 * TILE_WIDTH,
-                self.pos.1 as f32 * TILE_HEIGHT,
-                self.z_index as f32,
-            )
-            .with_scale(Vec3::splat(TILE_SCALE)),
-            ..default()
-        }
+    fn spawn(&self, child_builder: &mut ChildBuilder, _tile_assets: &TileAssets) {
+        child_builder.spawn(self.make_sprite_bundle());
     }
     fn pos(&self) -> (i32, i32) {
         self.pos
     }
+    fn collision(&self) -> TileCollision {
+        self.collision
+    }
 }
 
 impl ColorTile {
-    pub fn new(pos: (i32, i32), color: &'static str, z_index: i32) -> Self {
+    pub fn new(pos: (i32, i32), color: Color, z_index: i32) -> Self {
         Self {
             pos,
             color,
             z_index,
+            collision: TileCollision::Solid,
+        }
+    }
+
+    pub fn with_collision(mut self, collision: TileCollision) -> Self {
+        self.collision = collision;
+        self
+    }
+
+    pub fn make_sprite_bundle(&self) -> SpriteBundle {
+        SpriteBundle {
+            sprite: Sprite {
+                color: self.color,
+                ..default()
+            },
+            transform: Transform::from_xyz(
+                self.pos.0 as f32 * TILE_WIDTH,
+                self.pos.1 as f32 * TILE_HEIGHT,
+                self.z_index as f32,
+            )
+            .with_scale(Vec3::splat(TILE_SCALE)),
+            ..default()
         }
     }
 }
 
-#[derive(Debug)]
-pub enum TexturedTile {
-    Atlas {
-        pos: (i32, i32),
-        texture_path: &'static str,
-        tex_index: u32,
-        z_index: i32,
-    },
-    Single {
-        pos: (i32, i32),
-        texture_path: &'static str,
-        z_index: i32,
-    },
+/// A tile rendered from [`TileAssets::atlas`].
+#[derive(PartialEq, Debug)]
+pub struct TexturedTile {
+    pos: (i32, i32),
+    pub(crate) tex_index: usize,
+    pub(crate) z_index: i32,
+    pub(crate) collision: TileCollision,
 }
 
 impl Tile for TexturedTile {
-    fn make_sprite_bundle(&self) -> SpriteBundle {
-        todo!()
+    fn spawn(&self, child_builder: &mut ChildBuilder, tile_assets: &TileAssets) {
+        child_builder.spawn(self.make_sprite_sheet_bundle(tile_assets));
     }
 
     fn pos(&self) -> (i32, i32) {
-        todo!()
+        self.pos
+    }
+
+    fn collision(&self) -> TileCollision {
+        self.collision
     }
 }
 
 impl TexturedTile {
-    pub fn atlas(
-        pos: (i32, i32),
-        texture_path: &'static str,
-        tex_index: u32,
-        z_index: i32,
-    ) -> Self {
-        Self::Atlas {
+    pub fn new(pos: (i32, i32), tex_index: usize, z_index: i32) -> Self {
+        Self {
             pos,
-            texture_path,
             tex_index,
             z_index,
+            collision: TileCollision::Solid,
         }
     }
 
-    pub fn single(pos: (i32, i32), texture_path: &'static str, z_index: i32) -> Self {
-        Self::Single {
-            pos,
-            texture_path,
-            z_index,
+    pub fn with_collision(mut self, collision: TileCollision) -> Self {
+        self.collision = collision;
+        self
+    }
+
+    pub fn make_sprite_sheet_bundle(&self, tile_assets: &TileAssets) -> SpriteSheetBundle {
+        SpriteSheetBundle {
+            sprite: TextureAtlasSprite {
+                index: self.tex_index,
+                custom_size: Some(Vec2::new(TILE_WIDTH, TILE_HEIGHT)),
+                ..default()
+            },
+            texture_atlas: tile_assets.atlas.clone(),
+            transform: Transform::from_xyz(
+                self.pos.0 as f32 * TILE_WIDTH,
+                self.pos.1 as f32 * TILE_HEIGHT,
+                self.z_index as f32,
+            ),
+            ..default()
         }
     }
 }
@@ -0,0 +1,515 @@
+use bevy::utils::HashMap;
+use image::{ImageFormat, RgbaImage};
+
+use super::*;
+
+/// The image the adjacency rules are learned from.
+/// Every distinct colour is one kind of tile, fully transparent pixels are empty space.
+const SAMPLE: &[u8] = include_bytes!("../../assets/wfc/sample.png");
+
+/// How many times collapsing a chunk may go back on a decision that ran into a contradiction.
+const MAX_BACKTRACKS: u32 = 2000;
+
+// salts for `seeded_rng`, so corners, seams and chunks draw from independent streams
+const CORNER_SALT: u64 = 0;
+const VERTICAL_SEAM_SALT: u64 = 1;
+const HORIZONTAL_SEAM_SALT: u64 = 2;
+const CHUNK_SALT: u64 = 3;
+
+/// Right, up, left, down. The opposite of direction `d` is `(d + 2) % 4`.
+const DIRECTIONS: [(i32, i32); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+
+/// A set of possible tiles, one bit per tile kind.
+type TileSet = u64;
+
+/// Generates chunks with the Wave Function Collapse algorithm (simple tiled model),
+/// using adjacency rules learned from [`SAMPLE`].
+///
+/// Chunks have to agree with their neighbours without knowing about them, so the seams
+/// between chunks are collapsed first and only depend on the seed and their own position:
+/// 1. every chunk corner gets a tile picked from the corner's position
+/// 2. every seam between two corners is collapsed as a one-dimensional wave
+/// 3. the inside of the chunk is collapsed with its four seams fixed, backtracking on contradictions
+///
+/// Both chunks sharing a seam therefore see the exact same tiles on it. Seams only use tiles that
+/// can be connected to each other through a whole chunk, so the inside nearly always has a solution.
+pub struct WfcLevelGenerator {
+    seed: u32,
+    chunk_size: (u32, u32),
+    rules: WfcRules,
+    /// The tiles of the seams running along the x axis.
+    horizontal_seams: TileSet,
+    /// The tiles of the seams running along the y axis.
+    vertical_seams: TileSet,
+}
+
+impl WfcLevelGenerator {
+    /// The smallest chunks whose seams can be connected. In narrower chunks, the vertical seams
+    /// would be right next to each other, without room for tiles that fit both.
+    pub const MIN_CHUNK_SIZE: (u32, u32) = (2, 1);
+
+    /// Tile of the chunk corner at `pos` (in tile space).
+    fn corner(&self, pos: (i32, i32)) -> usize {
+        self.rules.pick(
+            self.horizontal_seams & self.vertical_seams,
+            &mut seeded_rng(self.seed, CORNER_SALT, pos),
+        )
+    }
+
+    /// Collapses the seam starting at the corner `from` and running `len` tiles along `dir`,
+    /// including both corners.
+    fn seam(&self, from: (i32, i32), dir: usize, len: usize) -> Vec<usize> {
+        let (dx, dy) = DIRECTIONS[dir];
+        let to = (from.0 + dx * len as i32, from.1 + dy * len as i32);
+        let (width, height, salt, tiles) = if dx != 0 {
+            (len + 1, 1, HORIZONTAL_SEAM_SALT, self.horizontal_seams)
+        } else {
+            (1, len + 1, VERTICAL_SEAM_SALT, self.vertical_seams)
+        };
+
+        let mut rng = seeded_rng(self.seed, salt, from);
+        let seam = || {
+            let mut wave = Wave::new(&self.rules, width, height);
+            for cell in 0..=len {
+                wave.constrain(cell, tiles);
+            }
+            wave
+        };
+        let mut wave = seam();
+        let first = 1 << self.corner(from);
+        let last = 1 << self.corner(to);
+        // if the two corners can't be connected, let go of the far one
+        if !(wave.constrain(0, first) && wave.constrain(len, last)) {
+            wave = seam();
+            wave.constrain(0, first);
+        }
+        // a seam is a path, so once it is arc consistent it can't run into a contradiction
+        wave.collapse(&mut rng);
+        wave.tiles()
+    }
+}
+
+impl LevelGenerator for WfcLevelGenerator {
+    type Tile = ColorTile;
+    fn new(seed: u32, config: &GeneratorConfig) -> Self {
+        let sample = image::load_from_memory_with_format(SAMPLE, ImageFormat::Png)
+            .expect("could not decode WFC sample");
+        let rules = WfcRules::from_image(&sample.into_rgba8());
+        let (width, height) = (config.chunk_size.0 as usize, config.chunk_size.1 as usize);
+        let (min_width, min_height) = Self::MIN_CHUNK_SIZE;
+        assert!(
+            width >= min_width as usize && height >= min_height as usize,
+            "chunks of {width}x{height} tiles are too small for the WFC generator"
+        );
+        Self {
+            seed,
+            chunk_size: config.chunk_size,
+            // horizontal seams are connected by the columns between them, and vertical ones by rows
+            horizontal_seams: rules.connectable(1, height),
+            vertical_seams: rules.connectable(0, width),
+            rules,
+        }
+    }
+
+    fn chunk_size(&self) -> (u32, u32) {
+        self.chunk_size
+    }
+
+    fn generate_chunk(&self, (start_x, start_y): (i32, i32)) -> Chunk<Self::Tile> {
+        let (width, height) = (self.chunk_size.0 as usize, self.chunk_size.1 as usize);
+        let tiles = self.collapse_chunk((start_x, start_y));
+
+        let mut data = Vec::new();
+        for x in 0..width {
+            for y in 0..height {
+                if let Some(color) = self.rules.colors[tiles[x + y * (width + 1)]] {
+                    data.push(ColorTile::new(
+                        (start_x + x as i32, start_y + y as i32),
+                        color,
+                        0,
+                    ));
+                }
+            }
+        }
+        Chunk {
+            ch_pos: (start_x / width as i32, start_y / height as i32),
+            width: width as u32,
+            height: height as u32,
+            data,
+        }
+    }
+}
+
+impl WfcLevelGenerator {
+    /// The tiles of the chunk starting at `start`, plus the first row and column of its upper and
+    /// right neighbours, row by row.
+    fn collapse_chunk(&self, (start_x, start_y): (i32, i32)) -> Vec<usize> {
+        let rules = &self.rules;
+        let (width, height) = (self.chunk_size.0 as usize, self.chunk_size.1 as usize);
+        let (end_x, end_y) = (start_x + width as i32, start_y + height as i32);
+
+        let bottom = self.seam((start_x, start_y), 0, width);
+        let top = self.seam((start_x, end_y), 0, width);
+        let left = self.seam((start_x, start_y), 1, height);
+        let right = self.seam((end_x, start_y), 1, height);
+
+        let (wave_width, wave_height) = (width + 1, height + 1);
+        // the chunk's own seams first, the ones it shares with its upper and right neighbours last
+        let seams = (0..wave_width)
+            .map(|x| (x, bottom[x]))
+            .chain((0..wave_height).map(|y| (y * wave_width, left[y])))
+            .chain((0..wave_width).map(|x| (x + height * wave_width, top[x])))
+            .chain((0..wave_height).map(|y| (width + y * wave_width, right[y])))
+            .collect::<Vec<_>>();
+
+        let chunk_pos = (
+            start_x.div_euclid(width as i32),
+            start_y.div_euclid(height as i32),
+        );
+        let mut rng = seeded_rng(self.seed, CHUNK_SALT, chunk_pos);
+        let mut budget = MAX_BACKTRACKS;
+        let mut wave = Wave::new(rules, wave_width, wave_height);
+        let seamed = seams
+            .iter()
+            .all(|(cell, tile)| wave.constrain(*cell, 1 << tile));
+        if seamed && wave.solve(&mut rng, &mut budget) {
+            return wave.tiles();
+        }
+
+        // should the seams still contradict each other, the chunk lets go of the tiles of
+        // the seams it shares with its upper and right neighbours that don't fit
+        let mut wave = Wave::new(rules, wave_width, wave_height);
+        for (cell, tile) in &seams {
+            let before = wave.cells.clone();
+            if !wave.constrain(*cell, 1 << tile) {
+                wave.cells = before;
+            }
+        }
+        wave.solve(&mut rng, &mut budget);
+        wave.tiles()
+    }
+}
+
+/// Adjacency rules of the simple tiled model.
+pub struct WfcRules {
+    /// The colour of each tile kind, `None` for empty space.
+    colors: Vec<Option<Color>>,
+    /// How often each tile kind appears in the sample.
+    weights: Vec<u32>,
+    /// `allowed[d][t]` are the tiles that may be placed next to tile `t` in direction `d`.
+    allowed: [Vec<TileSet>; 4],
+}
+
+impl WfcRules {
+    /// Learns the rules from every pair of neighbouring pixels in `sample`.
+    pub fn from_image(sample: &RgbaImage) -> Self {
+        let (width, height) = sample.dimensions();
+        let mut kinds = HashMap::new();
+        let mut colors = Vec::new();
+        let mut weights = Vec::new();
+
+        // image rows go down, world rows go up
+        let mut grid = vec![0; (width * height) as usize];
+        for (x, row, pixel) in sample.enumerate_pixels() {
+            let [r, g, b, a] = pixel.0;
+            let key = if a == 0 { [0; 4] } else { pixel.0 };
+            let kind = *kinds.entry(key).or_insert_with(|| {
+                colors.push((a != 0).then(|| Color::rgba_u8(r, g, b, a)));
+                weights.push(0);
+                colors.len() - 1
+            });
+            weights[kind] += 1;
+            grid[(x + (height - 1 - row) * width) as usize] = kind;
+        }
+        assert!(!colors.is_empty(), "WFC sample is empty");
+        assert!(
+            colors.len() <= TileSet::BITS as usize,
+            "WFC sample has more than {} colours",
+            TileSet::BITS
+        );
+
+        let mut allowed: [Vec<TileSet>; 4] = default();
+        allowed.iter_mut().for_each(|a| *a = vec![0; colors.len()]);
+        for x in 0..width as i32 {
+            for y in 0..height as i32 {
+                let tile = grid[(x + y * width as i32) as usize];
+                for (d, (dx, dy)) in DIRECTIONS.iter().enumerate() {
+                    let (nx, ny) = (x + dx, y + dy);
+                    if nx < 0 || ny < 0 || nx >= width as i32 || ny >= height as i32 {
+                        continue;
+                    }
+                    allowed[d][tile] |= 1 << grid[(nx + ny * width as i32) as usize];
+                }
+            }
+        }
+
+        Self {
+            colors,
+            weights,
+            allowed,
+        }
+    }
+
+    fn all(&self) -> TileSet {
+        TileSet::MAX >> (TileSet::BITS as usize - self.colors.len())
+    }
+
+    /// The tiles that can be `steps` tiles away from one of `from` in direction `d`.
+    fn reach(&self, from: TileSet, d: usize, steps: usize) -> TileSet {
+        (0..steps).fold(from, |tiles, _| {
+            (0..self.colors.len())
+                .filter(|t| tiles & (1 << t) != 0)
+                .fold(0, |acc, t| acc | self.allowed[d][t])
+        })
+    }
+
+    /// The most tiles out of which any two can be placed `steps` tiles apart in direction `d`,
+    /// with tiles fitting the rules in between.
+    fn connectable(&self, d: usize, steps: usize) -> TileSet {
+        let mut tiles = self.all();
+        loop {
+            let connected = (0..self.colors.len())
+                .filter(|t| tiles & (1 << t) != 0)
+                .filter(|t| {
+                    // the rules are symmetric, so this also connects the other tiles to `t`
+                    self.reach(1 << t, d, steps) & tiles == tiles
+                })
+                .fold(0, |acc, t| acc | 1 << t);
+            if connected == tiles || connected == 0 {
+                return if connected == 0 {
+                    1 << self.most_common()
+                } else {
+                    connected
+                };
+            }
+            tiles = connected;
+        }
+    }
+
+    /// Picks one tile out of `options`, weighted by how often it appears in the sample.
+    fn pick(&self, options: TileSet, rng: &mut impl Rng) -> usize {
+        let candidates = (0..self.colors.len()).filter(|t| options & (1 << t) != 0);
+        let total: u32 = candidates.clone().map(|t| self.weights[t]).sum();
+        let mut roll = rng.gen_range(0..total.max(1));
+        for t in candidates {
+            if roll < self.weights[t] {
+                return t;
+            }
+            roll -= self.weights[t];
+        }
+        options.trailing_zeros() as usize
+    }
+
+    /// The tile used when a wave could not be collapsed at all.
+    fn most_common(&self) -> usize {
+        (0..self.weights.len())
+            .max_by_key(|t| self.weights[*t])
+            .unwrap_or_default()
+    }
+}
+
+/// A grid of cells, each holding the tiles it could still become.
+#[derive(Clone)]
+struct Wave<'a> {
+    rules: &'a WfcRules,
+    width: usize,
+    height: usize,
+    cells: Vec<TileSet>,
+}
+
+impl<'a> Wave<'a> {
+    fn new(rules: &'a WfcRules, width: usize, height: usize) -> Self {
+        Self {
+            rules,
+            width,
+            height,
+            cells: vec![rules.all(); width * height],
+        }
+    }
+
+    /// Restricts a cell to `options` and propagates the change.
+    /// Returns `false` if this leads to a contradiction.
+    fn constrain(&mut self, cell: usize, options: TileSet) -> bool {
+        let constrained = self.cells[cell] & options;
+        if constrained == 0 {
+            return false;
+        }
+        if constrained != self.cells[cell] {
+            self.cells[cell] = constrained;
+            return self.propagate(cell);
+        }
+        true
+    }
+
+    fn propagate(&mut self, cell: usize) -> bool {
+        let mut stack = vec![cell];
+        while let Some(cell) = stack.pop() {
+            let (x, y) = ((cell % self.width) as i32, (cell / self.width) as i32);
+            for (d, (dx, dy)) in DIRECTIONS.iter().enumerate() {
+                let (nx, ny) = (x + dx, y + dy);
+                if nx < 0 || ny < 0 || nx >= self.width as i32 || ny >= self.height as i32 {
+                    continue;
+                }
+                let neighbor = nx as usize + ny as usize * self.width;
+                let compatible = (0..self.rules.colors.len())
+                    .filter(|t| self.cells[cell] & (1 << t) != 0)
+                    .fold(0, |acc, t| acc | self.rules.allowed[d][t]);
+                let constrained = self.cells[neighbor] & compatible;
+                if constrained == 0 {
+                    self.cells[neighbor] = 0;
+                    return false;
+                }
+                if constrained != self.cells[neighbor] {
+                    self.cells[neighbor] = constrained;
+                    stack.push(neighbor);
+                }
+            }
+        }
+        true
+    }
+
+    /// Collapses the cell with the fewest options until every cell is decided.
+    /// Returns `false` if this runs into a contradiction.
+    fn collapse(&mut self, rng: &mut impl Rng) -> bool {
+        loop {
+            let Some(fewest) = self
+                .cells
+                .iter()
+                .map(|c| c.count_ones())
+                .filter(|n| *n > 1)
+                .min()
+            else {
+                return true;
+            };
+            let candidates = (0..self.cells.len())
+                .filter(|c| self.cells[*c].count_ones() == fewest)
+                .collect::<Vec<_>>();
+            let cell = candidates[rng.gen_range(0..candidates.len())];
+            let tile = self.rules.pick(self.cells[cell], rng);
+            if !self.constrain(cell, 1 << tile) {
+                return false;
+            }
+        }
+    }
+
+    /// Collapses the cell with the fewest options until every cell is decided, going back on
+    /// decisions that run into a contradiction as long as there is `budget` left for it.
+    /// Returns `false` if there is no solution, or it couldn't be found within the budget.
+    fn solve(&mut self, rng: &mut impl Rng, budget: &mut u32) -> bool {
+        let Some(fewest) = self
+            .cells
+            .iter()
+            .map(|c| c.count_ones())
+            .filter(|n| *n > 1)
+            .min()
+        else {
+            return true;
+        };
+        let candidates = (0..self.cells.len())
+            .filter(|c| self.cells[*c].count_ones() == fewest)
+            .collect::<Vec<_>>();
+        let cell = candidates[rng.gen_range(0..candidates.len())];
+
+        let mut options = self.cells[cell];
+        while options != 0 {
+            let tile = self.rules.pick(options, rng);
+            options &= !(1 << tile);
+            let before = self.cells.clone();
+            if self.constrain(cell, 1 << tile) && self.solve(rng, budget) {
+                return true;
+            }
+            self.cells = before;
+            if *budget == 0 {
+                return false;
+            }
+            *budget -= 1;
+        }
+        false
+    }
+
+    /// The decided tile of every cell. Cells that are still undecided get their most likely
+    /// tile, cells left without any options (after a contradiction) the most common one.
+    fn tiles(&self) -> Vec<usize> {
+        self.cells
+            .iter()
+            .map(|c| match c {
+                0 => self.rules.most_common(),
+                c => (0..self.rules.colors.len())
+                    .filter(|t| c & (1 << t) != 0)
+                    .max_by_key(|t| self.rules.weights[*t])
+                    .unwrap_or_default(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generator(seed: u32) -> WfcLevelGenerator {
+        let config = GeneratorConfigLoader::parse(b"(chunk_size: (16, 16), layers: [])").unwrap();
+        WfcLevelGenerator::new(seed, &config)
+    }
+
+    #[test]
+    fn chunks_are_deterministic() {
+        let generator = generator(7);
+        let tiles = |chunk: Chunk<ColorTile>| {
+            chunk
+                .data
+                .iter()
+                .map(|tile| (tile.pos(), tile.color))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            tiles(generator.generate_chunk((0, 0))),
+            tiles(generator.generate_chunk((0, 0)))
+        );
+    }
+
+    #[test]
+    fn neighbouring_chunks_share_their_seams() {
+        for seed in 0..10 {
+            let generator = generator(seed);
+            let here = generator.collapse_chunk((0, 0));
+            let right = generator.collapse_chunk((16, 0));
+            let above = generator.collapse_chunk((0, 16));
+            // the corners are part of both the columns and the rows
+            for y in 0..=16 {
+                assert_eq!(here[16 + y * 17], right[y * 17], "seed {seed}, row {y}");
+            }
+            for x in 0..=16 {
+                assert_eq!(here[x + 16 * 17], above[x], "seed {seed}, column {x}");
+            }
+        }
+    }
+
+    #[test]
+    fn chunks_follow_the_rules_across_borders() {
+        for seed in 0..5 {
+            let generator = generator(seed);
+            let mut world = HashMap::new();
+            for i in -2..2 {
+                for j in -2..2 {
+                    let tiles = generator.collapse_chunk((i * 16, j * 16));
+                    for x in 0..16 {
+                        for y in 0..16 {
+                            world.insert((i * 16 + x, j * 16 + y), tiles[(x + y * 17) as usize]);
+                        }
+                    }
+                }
+            }
+            for (&(x, y), &tile) in &world {
+                for (d, (dx, dy)) in DIRECTIONS.iter().enumerate() {
+                    if let Some(&neighbor) = world.get(&(x + dx, y + dy)) {
+                        assert!(
+                            generator.rules.allowed[d][tile] & (1 << neighbor) != 0,
+                            "seed {seed}: {neighbor} can't be next to {tile} at ({x}, {y})"
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
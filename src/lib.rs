@@ -1,30 +1,93 @@
 #![allow(clippy::type_complexity)]
 
 mod audio;
+mod bindings;
+mod camera;
 mod character_controller;
 mod level_generator;
 mod loading;
 mod menu;
+mod pause;
+#[cfg(test)]
+mod testing;
 
 use crate::audio::InternalAudioPlugin;
+use crate::bindings::{BindingsPlugin, InputBindings, KeyboardScheme};
+use crate::camera::CameraPlugin;
 use crate::loading::LoadingPlugin;
 use crate::menu::MenuPlugin;
+use crate::pause::PausePlugin;
 
+use bevy::app::App;
 #[cfg(debug_assertions)]
 use bevy::diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin};
 use bevy::prelude::*;
-use bevy::{app::App, window::close_on_esc};
+use bevy::tasks::{block_on, AsyncComputeTaskPool, Task};
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
-use bevy_pancam::{PanCam, PanCamPlugin};
-use bevy_xpbd_2d::math::{Scalar, Vector};
+use bevy_xpbd_2d::math::{Scalar, Vector, PI};
 use bevy_xpbd_2d::prelude::*;
 use character_controller::{
-    CharacterController, CharacterControllerBundle, CharacterControllerPlugin,
+    CharacterController, CharacterControllerBundle, CharacterControllerPlugin, Dashing, JumpArc,
+    MovementBundle, PlayerInput,
+};
+use level_generator::cave_generator::CaveLevelGenerator;
+use level_generator::ore_generator::OreLevelGenerator;
+use level_generator::perlin_generator::{SimplePerlinLevelGenerator, TexturedPerlinLevelGenerator};
+use level_generator::terrain_generator::TerrainLevelGenerator;
+use level_generator::tile::{TILE_HEIGHT, TILE_WIDTH};
+use level_generator::wfc_generator::WfcLevelGenerator;
+use level_generator::{
+    ActiveLevelGenerator, ChunkGenerationSet, Hazard, LevelGenerator, LevelGeneratorPlugin,
+    ResetLevel, Seed, SpawnArea,
 };
-use level_generator::perlin_generator::SimplePerlinLevelGenerator;
-use level_generator::{LevelGeneratorPlugin, Seed};
 use rand::{thread_rng, Rng};
 
+// movement of the player
+const PLAYER_GRAVITY: Vector = Vector::new(0.0, -1000.0);
+const PLAYER_ACCELERATION: Scalar = 3050.0;
+const PLAYER_DAMPING: Scalar = 0.92;
+const PLAYER_JUMP_IMPULSE: Scalar = 400.0;
+const PLAYER_MAX_SLOPE_ANGLE: Scalar = PI / 6.0;
+const PLAYER_COYOTE_TIME: Scalar = 0.1;
+const PLAYER_JUMP_BUFFER: Scalar = 0.12;
+const PLAYER_JUMP_CUT: Scalar = 0.4;
+const PLAYER_AIR_JUMPS: u32 = 1;
+const PLAYER_DASH_SPEED: Scalar = 700.0;
+const PLAYER_DASH_DURATION: Scalar = 0.15;
+const PLAYER_DASH_COOLDOWN: Scalar = 0.6;
+const PLAYER_WALL_SLIDE_SPEED: Scalar = 120.0;
+const PLAYER_WALL_JUMP_IMPULSE: Vector = Vector::new(350.0, 420.0);
+
+/// The colors of the players, local co-op allows as many players as there are colors.
+const PLAYER_COLORS: [Color; 4] = [
+    Color::rgb(0.0, 0.0, 1.0),
+    Color::rgb(1.0, 0.0, 0.0),
+    Color::rgb(0.0, 0.8, 0.0),
+    Color::rgb(1.0, 0.0, 1.0),
+];
+
+/// The first player is spawned at the closest safe place to this.
+const PLAYER_SPAWN: Vec3 = Vec3::new(100., 1000., 10.);
+/// The space the player's capsule needs, with the ground at most 8 tiles below.
+const PLAYER_SPAWN_AREA: SpawnArea = SpawnArea {
+    width: 1,
+    height: 2,
+    max_drop: 8,
+};
+/// How many times the spawn search moves on to the chunks to either side, before it gives up.
+const PLAYER_SPAWN_SEARCHES: i32 = 3;
+
+/// The [`JumpArc`] of the player, for generators that need to know what the player can reach.
+fn player_jump_arc() -> JumpArc {
+    MovementBundle::new(
+        PLAYER_ACCELERATION,
+        PLAYER_DAMPING,
+        PLAYER_JUMP_IMPULSE,
+        PLAYER_MAX_SLOPE_ANGLE,
+    )
+    .jump_arc(PLAYER_GRAVITY)
+}
+
 #[derive(States, Default, Clone, Eq, PartialEq, Debug, Hash)]
 enum GameState {
     // During the loading State the LoadingPlugin will load our assets
@@ -34,67 +97,458 @@ enum GameState {
     Playing,
     // Here the menu is drawn and waiting for player interaction
     Menu,
-    // TODO: Add a pause state
+    // The game is frozen and the pause menu is drawn
+    Paused,
+    // Here the input bindings are shown and can be rebound
+    Controls,
 }
 
 pub struct InfiniJumpPlugin;
 
 impl Plugin for InfiniJumpPlugin {
     fn build(&self, app: &mut App) {
-        app.add_state::<GameState>().add_plugins((
-            LevelGeneratorPlugin::<SimplePerlinLevelGenerator, CharacterController>::seeded(
-                thread_rng().gen(),
-            ),
-            CharacterControllerPlugin,
-            TempPlugin,
-            LoadingPlugin,
-            MenuPlugin,
-            InternalAudioPlugin,
-        ));
+        app.add_state::<GameState>()
+            .insert_resource(player_jump_arc())
+            .add_plugins((
+                LevelGeneratorPlugin::<SimplePerlinLevelGenerator, CharacterController>::seeded(
+                    thread_rng().gen(),
+                ),
+                CharacterControllerPlugin,
+                BindingsPlugin,
+                TempPlugin,
+                CameraPlugin,
+                LoadingPlugin,
+                MenuPlugin,
+                PausePlugin,
+                InternalAudioPlugin,
+            ))
+            .add_systems(
+                Update,
+                cycle_level_generator.run_if(in_state(GameState::Playing)),
+            );
 
         #[cfg(debug_assertions)]
         {
             app.add_plugins(WorldInspectorPlugin::new())
                 .register_type::<Seed>()
-                .add_plugins((FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin::default()))
-                .add_systems(Update, close_on_esc);
+                .add_plugins((FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin::default()));
         }
     }
 }
 
+/// Switches to the next level generator on G, to compare them in the same session.
+fn cycle_level_generator(
+    mut generator: ResMut<ActiveLevelGenerator>,
+    input: Res<Input<KeyCode>>,
+    mut current: Local<usize>,
+) {
+    if !input.just_pressed(KeyCode::G) {
+        return;
+    }
+
+    *current = (*current + 1) % 6;
+    match *current {
+        0 => generator.swap::<SimplePerlinLevelGenerator>(),
+        1 => generator.swap::<TexturedPerlinLevelGenerator>(),
+        2 => generator.swap::<WfcLevelGenerator>(),
+        3 => generator.swap::<OreLevelGenerator>(),
+        4 => generator.swap::<CaveLevelGenerator>(),
+        _ => generator.swap::<TerrainLevelGenerator>(),
+    }
+    info!("Switched to level generator {}", generator.name());
+}
+
 // TODO: Temporary Plugin
 pub struct TempPlugin;
 
 impl Plugin for TempPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins(PanCamPlugin)
-            .add_systems(Startup, camera_setup)
-            .add_systems(OnEnter(GameState::Playing), spawn_player);
+        app
+            // the spawn point depends on the level generator, which is ready after chunk generation
+            .add_systems(
+                Update,
+                (
+                    spawn_player.run_if(not(any_with_component::<CharacterController>())),
+                    join_players.run_if(any_with_component::<CharacterController>()),
+                    respawn_player.run_if(on_event::<ResetLevel>()),
+                    hit_hazards,
+                    finish_respawns,
+                )
+                    .after(ChunkGenerationSet)
+                    .run_if(in_state(GameState::Playing)),
+            );
     }
 }
 
-fn camera_setup(mut commands: Commands) {
-    commands
-        .spawn(Camera2dBundle::default())
-        .insert(PanCam::default());
+/// Finds the safe spawn point closest to `near`. `None` if there is no safe place within
+/// [`PLAYER_SPAWN_SEARCHES`] searches around there.
+fn safe_spawn_point(generator: &(impl LevelGenerator + ?Sized), near: Vec3) -> Option<Vec3> {
+    let near = (
+        (near.x / TILE_WIDTH).round() as i32,
+        (near.y / TILE_HEIGHT).round() as i32,
+    );
+    // a search covers the chunk it starts in and its neighbours, the next ones start past them
+    let step = 3 * generator.chunk_size().0 as i32;
+    let (x, y) = std::iter::once(0)
+        .chain((1..=PLAYER_SPAWN_SEARCHES).flat_map(|i| [i, -i]))
+        .find_map(|i| {
+            generator.find_spawn_point((near.0 + i * step, near.1), &PLAYER_SPAWN_AREA)
+        })?;
+    // the center of the spawn area, tiles are centered on their position
+    Some(Vec3::new(
+        (x as f32 + (PLAYER_SPAWN_AREA.width - 1) as f32 / 2.0) * TILE_WIDTH,
+        (y as f32 + (PLAYER_SPAWN_AREA.height - 1) as f32 / 2.0) * TILE_HEIGHT,
+        PLAYER_SPAWN.z,
+    ))
 }
 
-#[allow(dead_code)]
-fn spawn_player(mut commands: Commands) {
-    commands
-        .spawn(SpriteBundle {
+/// Finds the safe spawn point closest to `near`, falling back to [`PLAYER_SPAWN`]
+/// if the generator has no safe place around there.
+fn player_spawn_point(generator: &(impl LevelGenerator + ?Sized), near: Vec3) -> Vec3 {
+    safe_spawn_point(generator, near).unwrap_or_else(|| {
+        warn!("No safe spawn point found, spawning the player at {PLAYER_SPAWN}.");
+        PLAYER_SPAWN
+    })
+}
+
+/// A search for the spawn point of a player, running on the [`AsyncComputeTaskPool`]
+/// because it may have to generate a few chunks. The player is moved there once it's done,
+/// or stays where it is if there's no spawn point.
+#[derive(Component)]
+struct Respawning(Task<Option<Vec3>>);
+
+impl Respawning {
+    /// Searches for the safe spawn point closest to `near`, which falls back to [`PLAYER_SPAWN`]
+    /// with `fallback`. `None` if the generator isn't ready yet.
+    fn search(generator: &ActiveLevelGenerator, near: Vec3, fallback: bool) -> Option<Self> {
+        let generator = generator.generator()?;
+        let task = AsyncComputeTaskPool::get().spawn(async move {
+            if fallback {
+                Some(player_spawn_point(&*generator, near))
+            } else {
+                safe_spawn_point(&*generator, near)
+            }
+        });
+        Some(Self(task))
+    }
+}
+
+/// A player at `spawn_point`, the `index`th player decides its color.
+fn player_bundle(spawn_point: Vec3, index: usize, input: PlayerInput) -> impl Bundle {
+    (
+        SpriteBundle {
             sprite: Sprite {
                 custom_size: Some(Vec2::new(20.0, 40.0)),
-                color: Color::rgb(0.0, 0.0, 1.0),
+                color: PLAYER_COLORS[index % PLAYER_COLORS.len()],
                 ..default()
             },
+            transform: Transform::from_translation(spawn_point),
             ..default()
-        })
-        .insert(TransformBundle::from_transform(Transform::from_xyz(
-            100., 1000., 10.,
-        )))
-        .insert(
-            CharacterControllerBundle::new(Collider::capsule(20.0, 12.5), Vector::NEG_Y * 1000.0)
-                .with_movement(3050.0, 0.92, 400.0, (30.0 as Scalar).to_radians()),
-        );
+        },
+        CharacterControllerBundle::new(Collider::capsule(20.0, 12.5), PLAYER_GRAVITY)
+            .with_movement(
+                PLAYER_ACCELERATION,
+                PLAYER_DAMPING,
+                PLAYER_JUMP_IMPULSE,
+                PLAYER_MAX_SLOPE_ANGLE,
+            )
+            .with_coyote_time(PLAYER_COYOTE_TIME)
+            .with_jump_buffer(PLAYER_JUMP_BUFFER)
+            .with_jump_cut(PLAYER_JUMP_CUT)
+            .with_air_jumps(PLAYER_AIR_JUMPS)
+            .with_dash(
+                PLAYER_DASH_SPEED,
+                PLAYER_DASH_DURATION,
+                PLAYER_DASH_COOLDOWN,
+            )
+            .with_wall_movement(PLAYER_WALL_SLIDE_SPEED, PLAYER_WALL_JUMP_IMPULSE),
+        input,
+    )
+}
+
+fn spawn_player(mut commands: Commands, generator: Res<ActiveLevelGenerator>) {
+    let Some(generator) = generator.generator() else {
+        return;
+    };
+    let spawn_point = player_spawn_point(&*generator, PLAYER_SPAWN);
+
+    commands.spawn(player_bundle(
+        spawn_point,
+        0,
+        PlayerInput {
+            keyboard: Some(KeyboardScheme::Full),
+            gamepad: None,
+        },
+    ));
+}
+
+/// Lets more players join for local co-op, next to the first player.
+/// Jump on a gamepad that has no player yet gives it to the keyboard player,
+/// or spawns a new player for it. Enter splits the keyboard between two players.
+fn join_players(
+    mut commands: Commands,
+    generator: Res<ActiveLevelGenerator>,
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    buttons: Res<Input<GamepadButton>>,
+    bindings: Res<InputBindings>,
+    mut players: Query<(&Transform, &mut PlayerInput)>,
+) {
+    let mut joining = Vec::new();
+
+    for gamepad in gamepads.iter() {
+        let pressed_jump = buttons.just_pressed(GamepadButton {
+            gamepad,
+            button_type: bindings.gamepad.jump,
+        });
+        if !pressed_jump
+            || players
+                .iter()
+                .any(|(_, input)| input.gamepad == Some(gamepad))
+        {
+            continue;
+        }
+
+        let solo_keyboard_player = players
+            .iter_mut()
+            .map(|(_, input)| input)
+            .find(|input| input.gamepad.is_none() && input.keyboard == Some(KeyboardScheme::Full));
+        if let Some(mut input) = solo_keyboard_player {
+            input.gamepad = Some(gamepad);
+        } else {
+            joining.push(PlayerInput {
+                keyboard: None,
+                gamepad: Some(gamepad),
+            });
+        }
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Return) {
+        let full_keyboard_player = players
+            .iter_mut()
+            .map(|(_, input)| input)
+            .find(|input| input.keyboard == Some(KeyboardScheme::Full));
+        if let Some(mut input) = full_keyboard_player {
+            input.keyboard = Some(KeyboardScheme::Left);
+            joining.push(PlayerInput {
+                keyboard: Some(KeyboardScheme::Right),
+                gamepad: None,
+            });
+        }
+    }
+
+    if joining.is_empty() {
+        return;
+    }
+
+    let near = players
+        .iter()
+        .next()
+        .map_or(PLAYER_SPAWN, |(transform, _)| transform.translation);
+    let Some(generator) = generator.generator() else {
+        return;
+    };
+    let spawn_point = player_spawn_point(&*generator, near);
+
+    for (index, input) in (players.iter().count()..).zip(joining) {
+        if index >= PLAYER_COLORS.len() {
+            warn!("There are no more than {} players.", PLAYER_COLORS.len());
+            break;
+        }
+        commands.spawn(player_bundle(spawn_point, index, input));
+        info!("Player {} joined.", index + 1);
+    }
+}
+
+fn respawn_player(
+    mut commands: Commands,
+    generator: Res<ActiveLevelGenerator>,
+    players: Query<Entity, With<CharacterController>>,
+) {
+    for player in &players {
+        if let Some(respawning) = Respawning::search(&generator, PLAYER_SPAWN, true) {
+            commands.entity(player).insert(respawning);
+        }
+    }
+}
+
+/// Sends players touching a [`Hazard`] back to the spawn point, unless they dash through it.
+fn hit_hazards(
+    mut commands: Commands,
+    generator: Res<ActiveLevelGenerator>,
+    collisions: Res<Collisions>,
+    hazards: Query<(), With<Hazard>>,
+    players: Query<
+        Entity,
+        (
+            With<CharacterController>,
+            Without<Dashing>,
+            Without<Respawning>,
+        ),
+    >,
+) {
+    for player in &players {
+        let hit = collisions.collisions_with_entity(player).any(|contacts| {
+            hazards.contains(contacts.entity1) || hazards.contains(contacts.entity2)
+        });
+        if !hit {
+            continue;
+        }
+        // the fallback isn't checked for hazards, it could send them right back into one
+        if let Some(respawning) = Respawning::search(&generator, PLAYER_SPAWN, false) {
+            commands.entity(player).insert(respawning);
+        }
+    }
+}
+
+/// Moves the players whose spawn point search is done.
+fn finish_respawns(
+    mut commands: Commands,
+    mut players: Query<(Entity, &mut Respawning, &mut Transform, &mut LinearVelocity)>,
+) {
+    for (player, mut respawning, mut transform, mut velocity) in &mut players {
+        if !respawning.0.is_finished() {
+            continue;
+        }
+        commands.entity(player).remove::<Respawning>();
+        let Some(spawn_point) = block_on(&mut respawning.0) else {
+            warn!("No safe spawn point found, leaving the player where it is.");
+            continue;
+        };
+        transform.translation = spawn_point;
+        velocity.0 = Vector::ZERO;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use level_generator::chunk::Chunk;
+    use level_generator::config::{ColliderStrategy, GeneratorConfig, GeneratorConfigLoader};
+    use level_generator::tile::{ColorTile, TileCollision};
+    use level_generator::LevelGenerator;
+
+    use super::*;
+
+    /// The surface of the ground, right below the tile [`PLAYER_SPAWN`] is in.
+    const SURFACE: i32 = 20;
+
+    /// Ground below [`SURFACE`], covered in hazards except for a gap a few tiles to the right.
+    struct Spiked((u32, u32));
+
+    impl LevelGenerator for Spiked {
+        type Tile = ColorTile;
+        fn new(_seed: u32, config: &GeneratorConfig) -> Self {
+            Self(config.chunk_size)
+        }
+
+        fn chunk_size(&self) -> (u32, u32) {
+            self.0
+        }
+
+        fn generate_chunk(&self, (start_x, start_y): (i32, i32)) -> Chunk<Self::Tile> {
+            let (width, height) = self.0;
+            let data = (start_x..start_x + width as i32)
+                .flat_map(|x| (start_y..start_y + height as i32).map(move |y| (x, y)))
+                .filter_map(|(x, y)| match y {
+                    y if y < SURFACE => Some(ColorTile::new((x, y), Color::WHITE, 0)),
+                    SURFACE if !(10..13).contains(&x) => Some(
+                        ColorTile::new((x, y), Color::RED, 0).with_collision(TileCollision::Hazard),
+                    ),
+                    _ => None,
+                })
+                .collect();
+            Chunk {
+                ch_pos: (
+                    start_x.div_euclid(width as i32),
+                    start_y.div_euclid(height as i32),
+                ),
+                width,
+                height,
+                data,
+            }
+        }
+    }
+
+    fn app() -> App {
+        let config = GeneratorConfigLoader::parse(b"(chunk_size: (16, 16), layers: [])").unwrap();
+        let mut generator = ActiveLevelGenerator::new::<Spiked>();
+        generator.create_generator(0, &config, Vec::new());
+
+        let mut app = testing::app();
+        app.add_systems(Update, (hit_hazards, finish_respawns));
+
+        // the chunks around the spawn point, with their colliders like the level generator spawns them
+        let chunks = generator.generator().unwrap();
+        for (i, j) in [-1, 0, 1].into_iter().flat_map(|i| [0, 1].map(|j| (i, j))) {
+            let chunk = chunks.generate_chunk((i * 16, j * 16));
+            for (translation, collider, collision) in
+                chunk.generate_colliders(ColliderStrategy::Rectangles)
+            {
+                let mut collider = app.world.spawn((
+                    TransformBundle::from_transform(Transform::from_translation(
+                        translation.extend(0.0),
+                    )),
+                    collider,
+                    RigidBody::Static,
+                ));
+                if collision == TileCollision::Hazard {
+                    collider.insert(Hazard);
+                }
+            }
+        }
+        app.insert_resource(generator);
+        app
+    }
+
+    #[test]
+    fn players_spawn_away_from_hazards() {
+        let mut app = app();
+        let generator = app.world.resource::<ActiveLevelGenerator>();
+        let spawn_point = player_spawn_point(&*generator.generator().unwrap(), PLAYER_SPAWN);
+        let tile = (spawn_point.x / TILE_WIDTH).round() as i32;
+        assert!((10..13).contains(&tile), "spawned in column {tile}");
+        let player = app
+            .world
+            .spawn(player_bundle(spawn_point, 0, PlayerInput::default()))
+            .id();
+
+        // a respawn would put the player back at the spawn point, standing still
+        let mut fell = false;
+        for _ in 0..90 {
+            app.update();
+            let transform = app.world.get::<Transform>(player).unwrap();
+            let velocity = app.world.get::<LinearVelocity>(player).unwrap();
+            let respawned = transform.translation == spawn_point && velocity.0 == Vector::ZERO;
+            assert!(!(fell && respawned), "respawned after falling");
+            fell |= transform.translation.y < spawn_point.y;
+        }
+        // standing on the ground in the gap, the capsule's center 22.5 above the ground's top
+        let standing = (SURFACE as f32 - 0.5) * TILE_HEIGHT + 22.5;
+        let height = app.world.get::<Transform>(player).unwrap().translation.y;
+        assert!((height - standing).abs() < 1.0, "at {height}");
+    }
+
+    #[test]
+    fn hazards_send_players_to_a_safe_spawn_point() {
+        let mut app = app();
+        // right above the spikes to the left of the gap
+        let above_spikes = Vec3::new(0.0, (SURFACE + 1) as f32 * TILE_HEIGHT, PLAYER_SPAWN.z);
+        let player = app
+            .world
+            .spawn(player_bundle(above_spikes, 0, PlayerInput::default()))
+            .id();
+
+        let mut respawned = false;
+        for _ in 0..120 {
+            app.update();
+            respawned |= app.world.get::<Respawning>(player).is_some();
+            if respawned && app.world.get::<Respawning>(player).is_none() {
+                break;
+            }
+        }
+        assert!(respawned, "never hit the spikes");
+        let x = app.world.get::<Transform>(player).unwrap().translation.x;
+        let tile = (x / TILE_WIDTH).round() as i32;
+        assert!((10..13).contains(&tile), "respawned in column {tile}");
+    }
 }
@@ -1,3 +1,5 @@
+use crate::level_generator::config::{GeneratorConfig, GeneratorConfigPlugin};
+use crate::level_generator::prefab::{Prefab, PrefabPlugin};
 use crate::GameState;
 use bevy::prelude::*;
 use bevy_asset_loader::prelude::*;
@@ -8,12 +10,15 @@ pub struct LoadingPlugin;
 /// This plugin loads all assets using [`AssetLoader`] from a third party bevy plugin
 impl Plugin for LoadingPlugin {
     fn build(&self, app: &mut App) {
-        app.add_loading_state(
-            LoadingState::new(GameState::Loading)
-                .continue_to_state(GameState::Menu)
-                .load_collection::<AudioAssets>()
-                .load_collection::<TextureAssets>(),
-        );
+        app.add_plugins((GeneratorConfigPlugin, PrefabPlugin))
+            .add_loading_state(
+                LoadingState::new(GameState::Loading)
+                    .continue_to_state(GameState::Menu)
+                    .load_collection::<AudioAssets>()
+                    .load_collection::<TextureAssets>()
+                    .load_collection::<TileAssets>()
+                    .load_collection::<LevelAssets>(),
+            );
     }
 }
 
@@ -32,3 +37,22 @@ pub struct TextureAssets {
     #[asset(path = "textures/github.png")]
     pub github: Handle<Image>,
 }
+
+#[derive(AssetCollection, Resource)]
+pub struct TileAssets {
+    #[asset(texture_atlas(tile_size_x = 32., tile_size_y = 32., columns = 4, rows = 1))]
+    #[asset(path = "textures/tiles.png")]
+    pub atlas: Handle<TextureAtlas>,
+}
+
+#[derive(AssetCollection, Resource)]
+pub struct LevelAssets {
+    #[asset(path = "level/default.generator.ron")]
+    pub generator_config: Handle<GeneratorConfig>,
+    /// Stamped into the chunks in this order, later ones win where they overlap.
+    #[asset(
+        paths("level/prefabs/ruin.prefab.ron", "level/prefabs/sky_shrine.prefab.ron"),
+        collection(typed)
+    )]
+    pub prefabs: Vec<Handle<Prefab>>,
+}
@@ -9,15 +9,22 @@ pub struct MenuPlugin;
 impl Plugin for MenuPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(OnEnter(GameState::Menu), setup_menu)
-            .add_systems(Update, click_play_button.run_if(in_state(GameState::Menu)))
+            .add_systems(
+                Update,
+                click_play_button.run_if(
+                    in_state(GameState::Menu)
+                        .or_else(in_state(GameState::Paused))
+                        .or_else(in_state(GameState::Controls)),
+                ),
+            )
             .add_systems(OnExit(GameState::Menu), cleanup_menu);
     }
 }
 
 #[derive(Component)]
-struct ButtonColors {
-    normal: Color,
-    hovered: Color,
+pub(crate) struct ButtonColors {
+    pub(crate) normal: Color,
+    pub(crate) hovered: Color,
 }
 
 impl Default for ButtonColors {
@@ -43,6 +50,7 @@ fn setup_menu(mut commands: Commands, textures: Res<TextureAssets>) {
                     flex_direction: FlexDirection::Column,
                     align_items: AlignItems::Center,
                     justify_content: JustifyContent::Center,
+                    row_gap: Val::Px(10.0),
                     ..default()
                 },
                 ..default()
@@ -77,6 +85,32 @@ fn setup_menu(mut commands: Commands, textures: Res<TextureAssets>) {
                         },
                     ));
                 });
+            children
+                .spawn((
+                    ButtonBundle {
+                        style: Style {
+                            width: Val::Px(200.0),
+                            height: Val::Px(50.0),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..Default::default()
+                        },
+                        background_color: ButtonColors::default().normal.into(),
+                        ..Default::default()
+                    },
+                    ButtonColors::default(),
+                    ChangeState(GameState::Controls),
+                ))
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        "Controls",
+                        TextStyle {
+                            font_size: 40.0,
+                            color: Color::rgb(0.9, 0.9, 0.9),
+                            ..default()
+                        },
+                    ));
+                });
         });
     commands
         .spawn((
@@ -175,7 +209,7 @@ fn setup_menu(mut commands: Commands, textures: Res<TextureAssets>) {
 }
 
 #[derive(Component)]
-struct ChangeState(GameState);
+pub(crate) struct ChangeState(pub(crate) GameState);
 
 #[derive(Component)]
 struct OpenLink(&'static str);
@@ -0,0 +1,168 @@
+use crate::character_controller::CharacterController;
+use crate::level_generator::chunk::ChunkMarker;
+use crate::level_generator::ResetLevel;
+use crate::menu::{ButtonColors, ChangeState};
+use crate::GameState;
+use bevy::prelude::*;
+use bevy_xpbd_2d::prelude::*;
+
+pub struct PausePlugin;
+
+/// This plugin is responsible for pausing the game on Escape or Start.
+/// While paused, physics time stands still and the pause menu is drawn,
+/// the menu is removed again when the state `GameState::Paused` is exited
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            toggle_pause.run_if(in_state(GameState::Playing).or_else(in_state(GameState::Paused))),
+        )
+        .add_systems(
+            OnEnter(GameState::Paused),
+            (pause_physics, setup_pause_menu),
+        )
+        .add_systems(
+            Update,
+            click_reset_button.run_if(in_state(GameState::Paused)),
+        )
+        .add_systems(
+            OnExit(GameState::Paused),
+            (resume_physics, cleanup_pause_menu),
+        )
+        .add_systems(
+            OnTransition {
+                from: GameState::Paused,
+                to: GameState::Menu,
+            },
+            despawn_level,
+        );
+    }
+}
+
+#[derive(Component)]
+struct PauseMenu;
+
+/// Resets the level when pressed, see [`ResetLevel`].
+#[derive(Component)]
+struct ResetButton {
+    reseed: bool,
+}
+
+fn toggle_pause(
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    buttons: Res<Input<GamepadButton>>,
+) {
+    let start_pressed = gamepads.iter().any(|gamepad| {
+        buttons.just_pressed(GamepadButton {
+            gamepad,
+            button_type: GamepadButtonType::Start,
+        })
+    });
+    if !keyboard_input.just_pressed(KeyCode::Escape) && !start_pressed {
+        return;
+    }
+
+    match state.get() {
+        GameState::Playing => next_state.set(GameState::Paused),
+        GameState::Paused => next_state.set(GameState::Playing),
+        _ => {}
+    }
+}
+
+fn pause_physics(mut time: ResMut<Time<Physics>>) {
+    time.pause();
+}
+
+fn resume_physics(mut time: ResMut<Time<Physics>>) {
+    time.unpause();
+}
+
+fn setup_pause_menu(mut commands: Commands) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    row_gap: Val::Px(10.0),
+                    ..default()
+                },
+                background_color: Color::rgba(0.0, 0.0, 0.0, 0.5).into(),
+                ..default()
+            },
+            PauseMenu,
+        ))
+        .with_children(|children| {
+            spawn_button(children, "Resume", ChangeState(GameState::Playing));
+            spawn_button(children, "Restart", ResetButton { reseed: false });
+            spawn_button(children, "New Level", ResetButton { reseed: true });
+            spawn_button(children, "Main Menu", ChangeState(GameState::Menu));
+        });
+}
+
+fn spawn_button(children: &mut ChildBuilder, text: &str, action: impl Bundle) {
+    let button_colors = ButtonColors::default();
+    children
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    width: Val::Px(240.0),
+                    height: Val::Px(50.0),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..Default::default()
+                },
+                background_color: button_colors.normal.into(),
+                ..Default::default()
+            },
+            button_colors,
+            action,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                text,
+                TextStyle {
+                    font_size: 40.0,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                    ..default()
+                },
+            ));
+        });
+}
+
+fn click_reset_button(
+    mut next_state: ResMut<NextState<GameState>>,
+    mut reset_event_writer: EventWriter<ResetLevel>,
+    interaction_query: Query<(&Interaction, &ResetButton), Changed<Interaction>>,
+) {
+    for (interaction, button) in &interaction_query {
+        if *interaction == Interaction::Pressed {
+            reset_event_writer.send(ResetLevel {
+                reseed: button.reseed,
+            });
+            next_state.set(GameState::Playing);
+        }
+    }
+}
+
+/// Despawns the player and all chunks.
+fn despawn_level(
+    mut commands: Commands,
+    level: Query<Entity, Or<(With<CharacterController>, With<ChunkMarker>)>>,
+) {
+    for entity in level.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn cleanup_pause_menu(mut commands: Commands, menu: Query<Entity, With<PauseMenu>>) {
+    for entity in menu.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -0,0 +1,34 @@
+//! Helpers shared by the tests of several modules.
+
+use std::time::Duration;
+
+use bevy::input::InputPlugin;
+use bevy::prelude::*;
+use bevy::time::TimeUpdateStrategy;
+use bevy_xpbd_2d::prelude::*;
+
+use crate::bindings::InputBindings;
+use crate::character_controller::CharacterControllerPlugin;
+use crate::GameState;
+
+/// An app with physics and the character controllers, that is already playing,
+/// and advances by a fixed frame time on every update.
+pub fn app() -> App {
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        InputPlugin,
+        TransformPlugin,
+        HierarchyPlugin,
+        PhysicsPlugins::default(),
+        CharacterControllerPlugin,
+    ))
+    .add_state::<GameState>()
+    // the default bindings, rather than the ones the BindingsPlugin loads from the user's config
+    .init_resource::<InputBindings>()
+    .insert_resource(NextState(Some(GameState::Playing)))
+    .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(
+        1.0 / 60.0,
+    )));
+    app
+}